@@ -71,6 +71,16 @@ impl Float3 {
     pub fn normalized(&self) -> Float3 {
         *self / self.length()
     }
+
+    /// Returns the component-wise minimum of the two vectors.
+    pub fn min(&self, rhs: &Float3) -> Float3 {
+        Float3::new(self.x().min(rhs.x()), self.y().min(rhs.y()), self.z().min(rhs.z()))
+    }
+
+    /// Returns the component-wise maximum of the two vectors.
+    pub fn max(&self, rhs: &Float3) -> Float3 {
+        Float3::new(self.x().max(rhs.x()), self.y().max(rhs.y()), self.z().max(rhs.z()))
+    }
 }
 
 // Overloads for binary arithmetic operators. They apply component-wise to all operands of type
@@ -84,6 +94,9 @@ impl_op_ex_commutative!(+ |lhs: &Float3, rhs: f32| -> Float3 {
 impl_op_ex!(- |lhs: &Float3, rhs: &Float3| -> Float3 {
     Float3 { data: [lhs.x() - rhs.x(), lhs.y() - rhs.y(), lhs.z() - rhs.z()] }
 });
+impl_op_ex!(- |lhs: &Float3, rhs: f32| -> Float3 {
+    Float3 { data: [lhs.x() - rhs, lhs.y() - rhs, lhs.z() - rhs] }
+});
 impl_op_ex!(* |lhs: &Float3, rhs: &Float3| -> Float3 {
     Float3 { data: [lhs.x() * rhs.x(), lhs.y() * rhs.y(), lhs.z() * rhs.z()] }
 });
@@ -116,22 +129,278 @@ impl_op!(/= |lhs: &mut Float3, rhs: f32| {
 
 //////////////////////////
 
+/// A 4x4 matrix in row-major order, which represents an affine transform of 3D space. Points are
+/// transformed as column vectors with a fourth coordinate of 1, and vectors with a fourth
+/// coordinate of 0, so vectors are not affected by translation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix4 {
+    pub data: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        Matrix4::scaling(&Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: &Vec3) -> Matrix4 {
+        let mut m = Matrix4::identity();
+        for row in 0..3 {
+            m.data[row][3] = offset.data[row];
+        }
+        m
+    }
+
+    /// Scales each axis by the corresponding component of `factors`.
+    pub fn scaling(factors: &Vec3) -> Matrix4 {
+        let mut data = [[0.0; 4]; 4];
+        for (i, row) in data.iter_mut().enumerate() {
+            row[i] = if i < 3 { factors.data[i] } else { 1.0 };
+        }
+        Matrix4 { data }
+    }
+
+    /// Rotates counter-clockwise by `angle` radians around `axis` (which doesn't need to be of unit
+    /// length) when looking from its tip towards the origin.
+    pub fn rotation(axis: &Vec3, angle: f32) -> Matrix4 {
+        let a = axis.normalized();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        Matrix4 {
+            data: [
+                [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+                [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+                [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut data = [[0.0; 4]; 4];
+        for (i, row) in data.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.data[j][i];
+            }
+        }
+        Matrix4 { data }
+    }
+
+    /// Returns the inverse of the matrix, or `None` if the matrix is singular. This uses
+    /// Gauss-Jordan elimination with partial pivoting.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut m = self.data;
+        let mut inverse = Matrix4::identity().data;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))?;
+            let pivot_value = m[pivot][column].abs();
+            if pivot_value.is_nan() || pivot_value <= f32::EPSILON * self.linear_max_abs() {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / m[column][column];
+            for j in 0..4 {
+                m[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                let factor = m[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix4 { data: inverse })
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::new(self.data[0][3], self.data[1][3], self.data[2][3])
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let row = |i: usize| Vec3::new(self.data[i][0], self.data[i][1], self.data[i][2]).dot(v);
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    // The largest magnitude of any element outside the translation column, which sets the scale
+    // for detecting singular matrices. A translation doesn't affect whether a matrix is singular,
+    // so a large offset mustn't make a small but well-conditioned scale look singular.
+    fn linear_max_abs(&self) -> f32 {
+        let linear = self.data[..3].iter().flat_map(|row| &row[..3]);
+        linear.chain(&self.data[3]).fold(0.0, |max, v| v.abs().max(max))
+    }
+}
+
+// Matrix multiplication. The product `a * b` applies `b` first and then `a`.
+impl_op_ex!(* |lhs: &Matrix4, rhs: &Matrix4| -> Matrix4 {
+    let mut data = [[0.0; 4]; 4];
+    for (i, row) in data.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| lhs.data[i][k] * rhs.data[k][j]).sum();
+        }
+    }
+    Matrix4 { data }
+});
+
+//////////////////////////
+
 pub struct Ray {
     pub origin: Point3,
 
     /// The direction vector is not guaranteed to be normalized and the caller must take care to
     /// normalize it when necessary.
     pub direction: Vec3,
+
+    /// The moment at which the ray is cast, within the shutter interval of the camera. Moving
+    /// objects are intersected at their position at this time.
+    pub time: f32,
 }
 
 impl Ray {
     /// Constructs a ray with a normalized direction vector.
     #[allow(dead_code)]
-    pub fn with_unit_direction(origin: &Point3, direction: &Vec3) -> Ray {
-        Ray { origin: *origin, direction: direction.normalized() }
+    pub fn with_unit_direction(origin: &Point3, direction: &Vec3, time: f32) -> Ray {
+        Ray { origin: *origin, direction: direction.normalized(), time }
     }
 
     pub fn at(&self, t: f32) -> Point3 {
         self.origin + t * self.direction
     }
 }
+
+//////////////////////////
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    /// Constructs the smallest box that contains both of the given points.
+    pub fn new(a: &Point3, b: &Point3) -> Aabb {
+        Aabb { min: a.min(b), max: a.max(b) }
+    }
+
+    /// Returns the smallest box that encloses both `self` and `other`.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.min(&other.min), max: self.max.max(&other.max) }
+    }
+
+    /// Returns a copy of the box, grown such that no side is thinner than `delta`. This avoids
+    /// degenerate (zero-volume) boxes around planar primitives. `delta` is scaled by the magnitude
+    /// of the coordinates along the thin axis so that the padding doesn't get lost to rounding.
+    pub fn padded(&self, delta: f32) -> Aabb {
+        let mut min = self.min;
+        let mut max = self.max;
+        for axis in 0..3 {
+            let delta = delta * min.data[axis].abs().max(max.data[axis].abs()).max(1.0);
+            if max.data[axis] - min.data[axis] < delta {
+                min.data[axis] -= delta / 2.0;
+                max.data[axis] += delta / 2.0;
+            }
+        }
+        Aabb { min, max }
+    }
+
+    /// Returns the eight corners of the box.
+    pub fn corners(&self) -> [Point3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i: usize| {
+            let pick = |axis: usize| {
+                if i & (1 << axis) == 0 {
+                    self.min.data[axis]
+                } else {
+                    self.max.data[axis]
+                }
+            };
+            Point3::new(pick(0), pick(1), pick(2))
+        })
+    }
+
+    /// Returns the smallest box that encloses the transformed corners of this box.
+    pub fn transformed(&self, m: &Matrix4) -> Aabb {
+        let corners = self.corners().map(|c| m.transform_point(&c));
+        let first = Aabb::new(&corners[0], &corners[0]);
+        corners[1..].iter().fold(first, |b, c| b.union(&Aabb::new(c, c)))
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    /// Returns true if the ray overlaps the box within [t_min, t_max]. This uses the "slab" method
+    /// described in section 3.4 of "Ray Tracing: The Next Week".
+    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction.data[axis];
+            let mut t0 = (self.min.data[axis] - ray.origin.data[axis]) * inv_d;
+            let mut t1 = (self.max.data[axis] - ray.origin.data[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn transforms_points_and_vectors() {
+        let m = Matrix4::translation(&Vec3::new(1.0, 2.0, 3.0))
+            * Matrix4::rotation(&Vec3::new(0.0, 0.0, 2.0), std::f32::consts::FRAC_PI_2)
+            * Matrix4::scaling(&Vec3::new(2.0, 1.0, 1.0));
+        let p = Point3::new(1.0, 0.0, 0.0);
+        assert_near(&m.transform_point(&p), &Point3::new(1.0, 4.0, 3.0));
+        assert_near(&m.transform_vector(&p), &Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let m = Matrix4::translation(&Vec3::new(-4.0, 0.5, 2.0))
+            * Matrix4::rotation(&Vec3::new(1.0, 1.0, 0.0), 0.7)
+            * Matrix4::scaling(&Vec3::new(0.5, 3.0, 1.5));
+        let inverse = m.inverse().unwrap();
+        let p = Point3::new(0.3, -2.0, 5.0);
+        assert_near(&inverse.transform_point(&m.transform_point(&p)), &p);
+        assert_near(&(m * inverse).transform_point(&p), &p);
+        assert!(Matrix4::scaling(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn inverts_small_scales_with_large_translations() {
+        let m = Matrix4::translation(&Vec3::new(1e5, 0.0, 0.0))
+            * Matrix4::scaling(&Vec3::new(0.01, 0.01, 0.01));
+        let inverse = m.inverse().unwrap();
+        let p = Point3::new(0.5, -2.0, 3.0);
+        // Points near the offset are only precise to about 0.01, which the inverse scales up.
+        let q = inverse.transform_point(&m.transform_point(&p));
+        assert!((q - p).length() < 1.0, "{:?}", q);
+        let v = inverse.transform_vector(&Vec3::new(0.01, 0.0, 0.0));
+        assert_near(&v, &Vec3::new(1.0, 0.0, 0.0));
+    }
+}
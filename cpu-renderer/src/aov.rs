@@ -0,0 +1,220 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{algebra::Vec3, color::RgbFloat};
+use {
+    image::Rgb32FImage,
+    std::{collections::HashMap, str::FromStr},
+};
+
+/// An auxiliary output (arbitrary output variable) that describes the surfaces seen through each
+/// pixel, for compositing and denoising.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aov {
+    /// The reflectance of the first surface hit by each camera ray, without any lighting.
+    Albedo,
+
+    /// The world-space shading normal of the first surface, facing the camera.
+    Normal,
+
+    /// The distance from the camera to the first surface, or infinity if there is none.
+    Depth,
+
+    /// The index of the top-level scene object that was hit plus one, or 0 for the background.
+    ObjectId,
+
+    /// Numbers the materials in the order in which they first appear in the image, row by row,
+    /// starting at 1. The background is 0.
+    MaterialId,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "albedo" => Ok(Aov::Albedo),
+            "normal" => Ok(Aov::Normal),
+            "depth" => Ok(Aov::Depth),
+            "object-id" => Ok(Aov::ObjectId),
+            "material-id" => Ok(Aov::MaterialId),
+            _ => Err("AOV must be 'albedo', 'normal', 'depth', 'object-id' or 'material-id'"),
+        }
+    }
+}
+
+/// The auxiliary outputs of a single pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct AovPixel {
+    /// The average albedo of the samples. Samples that miss the scene count as black.
+    pub albedo: RgbFloat,
+
+    /// The average normal of the samples, which is shorter than unit length along silhouettes and
+    /// zero if no sample hits the scene.
+    pub normal: Vec3,
+
+    /// The average distance of the samples that hit the scene.
+    pub depth: f32,
+
+    /// The object and material hit by most samples.
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+/// The auxiliary outputs of every pixel of an image. See `Renderer::render_aovs`.
+pub struct Aovs {
+    width: u32,
+    height: u32,
+
+    // In row-major order.
+    pixels: Vec<AovPixel>,
+}
+
+impl Aovs {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, col: u32, row: u32) -> &AovPixel {
+        &self.pixels[row as usize * self.width as usize + col as usize]
+    }
+
+    /// Returns the values of an output as an image. Single-valued outputs are repeated in all three
+    /// channels, and IDs are stored as floating point numbers, which are exact up to 2^24.
+    pub fn image(&self, aov: Aov) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.height, |col, row| {
+            let pixel = self.pixel(col, row);
+            match aov {
+                Aov::Albedo => pixel.albedo.into(),
+                Aov::Normal => image::Rgb(pixel.normal.data),
+                Aov::Depth => image::Rgb([pixel.depth; 3]),
+                Aov::ObjectId => image::Rgb([pixel.object_id as f32; 3]),
+                Aov::MaterialId => image::Rgb([pixel.material_id as f32; 3]),
+            }
+        })
+    }
+
+    /// Creates the outputs from the pixels in row-major order, each with the material key of the
+    /// most common hit (see `AovPixel::from_samples`). The keys are numbered by their first
+    /// appearance.
+    pub(crate) fn new(width: u32, height: u32, pixels: Vec<(AovPixel, Option<usize>)>) -> Aovs {
+        let mut material_ids = HashMap::new();
+        let pixels = pixels
+            .into_iter()
+            .map(|(pixel, material)| match material {
+                None => pixel,
+                Some(material) => {
+                    let next_id = material_ids.len() as u32 + 1;
+                    AovPixel {
+                        material_id: *material_ids.entry(material).or_insert(next_id),
+                        ..pixel
+                    }
+                }
+            })
+            .collect();
+        Aovs { width, height, pixels }
+    }
+}
+
+impl AovPixel {
+    /// Combines the samples of a pixel. Returns the pixel without its material ID and the
+    /// `material` key of the most common hit, if any.
+    pub(crate) fn from_samples(samples: &[AovSample]) -> (AovPixel, Option<usize>) {
+        let mut pixel = AovPixel {
+            albedo: RgbFloat::black(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            depth: f32::INFINITY,
+            object_id: 0,
+            material_id: 0,
+        };
+        let n = samples.len().max(1) as f32;
+        let hits: Vec<&AovHit> = samples.iter().flatten().collect();
+        for hit in hits.iter() {
+            pixel.albedo += hit.albedo / n;
+            pixel.normal += hit.normal / n;
+        }
+        if !hits.is_empty() {
+            pixel.depth = hits.iter().map(|hit| hit.depth).sum::<f32>() / hits.len() as f32;
+        }
+
+        // The most common object and material, preferring earlier samples on ties.
+        let key = |sample: &AovSample| sample.as_ref().map(|hit| (hit.object, hit.material));
+        let count = |sample: &AovSample| samples.iter().filter(|s| key(s) == key(sample)).count();
+        match samples.iter().rev().max_by_key(|sample| count(sample)) {
+            Some(Some(hit)) => {
+                (AovPixel { object_id: hit.object + 1, ..pixel }, Some(hit.material))
+            }
+            _ => (pixel, None),
+        }
+    }
+}
+
+/// The surface seen by a single camera ray, or `None` if the ray misses the scene.
+pub(crate) type AovSample = Option<AovHit>;
+
+pub(crate) struct AovHit {
+    pub albedo: RgbFloat,
+    pub normal: Vec3,
+    pub depth: f32,
+    pub object: u32,
+
+    /// Distinguishes the material from all other materials in the scene.
+    pub material: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(object: u32, material: usize) -> AovSample {
+        Some(AovHit {
+            albedo: RgbFloat::gray(0.5),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            depth: object as f32,
+            object,
+            material,
+        })
+    }
+
+    #[test]
+    fn pixels_average_samples_and_pick_most_common_ids() {
+        let samples = [
+            vec![None, hit(3, 100), None, hit(3, 100)],
+            vec![hit(1, 200), hit(3, 100), hit(3, 100), hit(1, 200)],
+            vec![None, None, None, None],
+        ];
+        let pixels = samples.iter().map(|samples| AovPixel::from_samples(samples)).collect();
+        let aovs = Aovs::new(3, 1, pixels);
+
+        let pixel = aovs.pixel(0, 0);
+        assert_eq!((pixel.albedo.r(), pixel.normal.y(), pixel.depth), (0.25, 0.5, 3.0));
+        // Ties go to the earlier sample: the background.
+        assert_eq!((pixel.object_id, pixel.material_id), (0, 0));
+
+        let pixel = aovs.pixel(1, 0);
+        assert_eq!((pixel.albedo.r(), pixel.depth), (0.5, 2.0));
+        assert_eq!((pixel.object_id, pixel.material_id), (2, 1));
+
+        let pixel = aovs.pixel(2, 0);
+        assert_eq!((pixel.normal.length(), pixel.depth), (0.0, f32::INFINITY));
+        assert_eq!(aovs.image(Aov::ObjectId).get_pixel(1, 0).0, [2.0; 3]);
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Aabb, Point3, Ray, Vec3},
+    scene::{HitRecord, Hittable, Scene},
+};
+
+/// Number of buckets used to approximate the surface area heuristic when choosing a split plane.
+const SAH_BUCKETS: usize = 16;
+
+/// A node in a bounding volume hierarchy. Interior nodes and leaves are not distinguished: the
+/// children of a node are either other `BvhNode`s or scene objects.
+pub struct BvhNode {
+    bounds: Aabb,
+    left: Box<dyn Hittable>,
+
+    /// `None` if this node holds a single object.
+    right: Option<Box<dyn Hittable>>,
+}
+
+impl BvhNode {
+    /// Builds a hierarchy over the given objects, which must be non-empty and all bounded.
+    pub fn new(mut objects: Vec<Box<dyn Hittable>>) -> BvhNode {
+        assert!(!objects.is_empty(), "cannot build a BVH over an empty list");
+        let boxes: Vec<Aabb> = objects
+            .iter()
+            .map(|o| o.bounding_box().expect("BVH objects must have a bounding box"))
+            .collect();
+        let bounds = boxes[1..].iter().fold(boxes[0], |acc, b| acc.union(b));
+
+        match objects.len() {
+            1 => BvhNode { bounds, left: objects.pop().unwrap(), right: None },
+            2 => {
+                let right = objects.pop();
+                BvhNode { bounds, left: objects.pop().unwrap(), right }
+            }
+            _ => {
+                let mut entries: Vec<(Aabb, Box<dyn Hittable>)> =
+                    boxes.into_iter().zip(objects).collect();
+                let mid = partition_sah(&mut entries);
+                let right: Vec<Box<dyn Hittable>> =
+                    entries.split_off(mid).into_iter().map(|(_, o)| o).collect();
+                let left: Vec<Box<dyn Hittable>> = entries.into_iter().map(|(_, o)| o).collect();
+                BvhNode {
+                    bounds,
+                    left: Box::new(BvhNode::new(left)),
+                    right: Some(Box::new(BvhNode::new(right))),
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        if !self.bounds.hit(ray, t_min, t_max) {
+            return None;
+        }
+        let left = self.left.bounded_hit(ray, t_min, t_max);
+        let right = self
+            .right
+            .as_ref()
+            .and_then(|r| r.bounded_hit(ray, t_min, left.as_ref().map_or(t_max, |h| h.t)));
+        right.or(left)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    // The sum of the densities of the objects that the direction passes through. This is the
+    // density of a light made of the objects, as long as the density of each object accounts for
+    // the probability of picking it.
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let ray = Ray { origin: *origin, direction: *direction, time: 0.0 };
+        if !self.bounds.hit(&ray, 0.0, f32::INFINITY) {
+            return 0.0;
+        }
+        let right = self.right.as_ref().map_or(0.0, |r| r.direction_pdf(origin, direction));
+        self.left.direction_pdf(origin, direction) + right
+    }
+}
+
+/// Builds a BVH over all bounded objects in `scene`. The returned scene contains the root of the
+/// hierarchy followed by any unbounded objects, which are tested against every ray.
+pub fn build_bvh(scene: Scene) -> Scene {
+    let (bounded, mut unbounded): (Scene, Scene) =
+        scene.into_iter().partition(|o| o.bounding_box().is_some());
+    let mut result: Scene = Vec::new();
+    if !bounded.is_empty() {
+        result.push(Box::new(BvhNode::new(bounded)));
+    }
+    result.append(&mut unbounded);
+    result
+}
+
+// Reorders `entries` along the axis of greatest centroid extent and returns the index that splits
+// them into two non-empty halves with the lowest surface area heuristic cost. The cost of a split
+// is approximated by binning the centroids into `SAH_BUCKETS` equally sized buckets.
+fn partition_sah<T>(entries: &mut [(Aabb, T)]) -> usize {
+    let centroids = entries.iter().map(|(b, _)| b.centroid());
+    let first = entries[0].0.centroid();
+    let (cmin, cmax) = centroids.fold((first, first), |(lo, hi), c| (lo.min(&c), hi.max(&c)));
+    let extent = cmax - cmin;
+    let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+        0
+    } else if extent.y() > extent.z() {
+        1
+    } else {
+        2
+    };
+
+    entries.sort_by(|(a, _), (b, _)| {
+        a.centroid().data[axis].partial_cmp(&b.centroid().data[axis]).unwrap()
+    });
+
+    // All centroids coincide; split the list in half.
+    if extent.data[axis] <= 0.0 {
+        return entries.len() / 2;
+    }
+
+    let bucket_of = |b: &Aabb| {
+        let offset = (b.centroid().data[axis] - cmin.data[axis]) / extent.data[axis];
+        ((offset * SAH_BUCKETS as f32) as usize).min(SAH_BUCKETS - 1)
+    };
+    let mut buckets: [Option<(Aabb, usize)>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+    for (b, _) in entries.iter() {
+        let bucket = &mut buckets[bucket_of(b)];
+        *bucket = Some(match bucket {
+            Some((bounds, count)) => (bounds.union(b), *count + 1),
+            None => (*b, 1),
+        });
+    }
+
+    // Evaluate the cost of splitting after each bucket and pick the cheapest one. Since the
+    // entries are sorted along the split axis, the number of entries to the left of the plane is
+    // also the partition index.
+    let mut best: Option<(f32, usize)> = None;
+    for split in 1..SAH_BUCKETS {
+        let sum = |range: &[Option<(Aabb, usize)>]| {
+            range.iter().flatten().fold(None, |acc: Option<(Aabb, usize)>, (b, n)| {
+                Some(acc.map_or((*b, *n), |(ab, an)| (ab.union(b), an + n)))
+            })
+        };
+        if let (Some((lb, ln)), Some((rb, rn))) = (sum(&buckets[..split]), sum(&buckets[split..])) {
+            let cost = lb.surface_area() * ln as f32 + rb.surface_area() * rn as f32;
+            if best.is_none_or(|(c, _)| cost < c) {
+                best = Some((cost, ln));
+            }
+        }
+    }
+    best.map_or(entries.len() / 2, |(_, mid)| mid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::RgbFloat, material::Lambertian, random::Rng, scene::Sphere};
+
+    fn random_spheres(rng: &Rng, count: usize) -> Vec<Sphere> {
+        let material = Lambertian::new(RgbFloat::gray(0.5));
+        (0..count)
+            .map(|_| Sphere {
+                center: 20.0 * rng.random_vec3() - 10.0,
+                radius: 0.1 + rng.random_float(),
+                material: material.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bvh_matches_linear_scan() {
+        let rng = Rng::new(0);
+        let spheres = random_spheres(&rng, 500);
+        let flat: Scene =
+            spheres.iter().cloned().map(|s| Box::new(s) as Box<dyn Hittable>).collect();
+        let bvh =
+            build_bvh(spheres.into_iter().map(|s| Box::new(s) as Box<dyn Hittable>).collect());
+
+        let mut hits = 0;
+        for _ in 0..10000 {
+            let ray = Ray {
+                origin: 30.0 * rng.random_vec3() - 15.0,
+                direction: rng.sample_sphere(),
+                time: 0.0,
+            };
+            match (flat.hit(&ray), bvh.hit(&ray)) {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    hits += 1;
+                    assert_eq!(a.t, b.t);
+                    assert_eq!(a.point.data, b.point.data);
+                    assert_eq!(a.normal.data, b.normal.data);
+                    assert_eq!(a.is_front_face, b.is_front_face);
+                }
+                (a, b) => panic!("hit mismatch: linear={} bvh={}", a.is_some(), b.is_some()),
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn bvh_bounds_enclose_objects() {
+        let rng = Rng::new(0);
+        let spheres = random_spheres(&rng, 3);
+        let expected =
+            spheres.iter().map(|s| s.bounding_box().unwrap()).reduce(|a, b| a.union(&b)).unwrap();
+        let bvh =
+            BvhNode::new(spheres.into_iter().map(|s| Box::new(s) as Box<dyn Hittable>).collect());
+        let bounds = bvh.bounding_box().unwrap();
+        assert_eq!(bounds.min.data, expected.min.data);
+        assert_eq!(bounds.max.data, expected.max.data);
+    }
+}
@@ -20,6 +20,11 @@ pub struct CameraParams {
     /// Aperture and focus distance control the depth of field (defocus distance) effect.
     pub aperture: f32,
     pub focus_distance: f32,
+
+    /// Rays are cast at random times between the opening and closing of the shutter, which blurs
+    /// moving objects. The shutter is instantaneous if both are equal.
+    pub shutter_open: f32,
+    pub shutter_close: f32,
 }
 
 pub struct Camera {
@@ -37,6 +42,9 @@ pub struct Camera {
     u: Vec3, v: Vec3, #[allow(dead_code)] w: Vec3,
 
     lens_radius: f32,
+
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
@@ -63,19 +71,30 @@ impl Camera {
                 - params.focus_distance * w,
             u, v, w,
             lens_radius: params.aperture / 2.0,
+            shutter_open: params.shutter_open,
+            shutter_close: params.shutter_close,
         }
     }
 
     /// Returns a world-space primary ray from the eye of the camera towards the viewport coordinates (s, t).
     /// The only valid coordinates are in [0.0, 1.0]
-    pub fn ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * Rng::sample_disk();
+    pub fn ray(&self, s: f32, t: f32, rng: &Rng) -> Ray {
+        let rd = self.lens_radius * rng.sample_disk();
         let offset = self.u * rd.x() + self.v * rd.y();
+
+        // Don't consume a random number for an instantaneous shutter, which keeps the samples of
+        // static renders unchanged.
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + rng.random_float() * (self.shutter_close - self.shutter_open)
+        } else {
+            self.shutter_open
+        };
         Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + s * self.horizontal + t * self.vertical
                 - self.origin
                 - offset,
+            time,
         }
     }
 }
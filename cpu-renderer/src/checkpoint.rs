@@ -0,0 +1,251 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    color::RgbFloat,
+    film::{Film, Pixel},
+    render::RenderParams,
+    sampler::SamplerType,
+};
+use {
+    anyhow::{anyhow, Context, Result},
+    std::{
+        fs::{self, File},
+        io::{BufReader, BufWriter, Read, Write},
+        path::Path,
+    },
+};
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+/// The accumulated samples of an unfinished render, which can be saved to a file and resumed later
+/// by rendering more samples into its film.
+pub struct Checkpoint {
+    pub film: Film,
+
+    /// Identifies the scene and the settings that the samples were rendered with. See
+    /// `render_hash`.
+    pub hash: u64,
+    pub seed: u64,
+    pub max_depth: u32,
+    pub samples_per_pixel: u32,
+}
+
+/// Hashes the inputs that determine the value of every sample: `scene_id` (which should uniquely
+/// describe the scene, e.g. the contents of a scene file), the image dimensions, the maximum path
+/// depth, the seed, the sampler and the filter. Samples can only be combined with those of a render
+/// with the same hash.
+///
+/// The number of samples per pixel and per pass are not included, since they don't affect the
+/// value of individual samples, except for the stratified sampler.
+pub fn render_hash(scene_id: &[u8], width: u32, height: u32, params: &RenderParams) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(&(scene_id.len() as u64).to_le_bytes());
+    hasher.write(scene_id);
+    hasher.write(&width.to_le_bytes());
+    hasher.write(&height.to_le_bytes());
+    hasher.write(&params.max_depth.to_le_bytes());
+    hasher.write(&params.seed.to_le_bytes());
+    hasher.write(params.sampler.name().as_bytes());
+    hasher.write(params.filter.kind.name().as_bytes());
+    hasher.write(&params.filter.radius.to_le_bytes());
+    if params.sampler == SamplerType::Stratified {
+        hasher.write(&params.samples_per_pixel.to_le_bytes());
+    }
+    hasher.finish()
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to `path`. The file is replaced atomically so that a render that is
+    /// killed while saving doesn't lose the previous checkpoint.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let context = || format!("failed to write checkpoint \"{}\"", path.display());
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temp_path).with_context(context)?);
+        self.write(&mut writer).and_then(|_| writer.flush()).with_context(context)?;
+        drop(writer);
+        fs::rename(&temp_path, path).with_context(context)
+    }
+
+    /// Reads a checkpoint written by `save`.
+    pub fn load(path: &Path) -> Result<Checkpoint> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to open checkpoint \"{}\"", path.display()))?;
+        Checkpoint::read(&mut BufReader::new(file))
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.hash.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&self.max_depth.to_le_bytes())?;
+        writer.write_all(&self.samples_per_pixel.to_le_bytes())?;
+        writer.write_all(&self.film.width().to_le_bytes())?;
+        writer.write_all(&self.film.height().to_le_bytes())?;
+        for pixel in self.film.pixels() {
+            let sum = &pixel.sum;
+            for c in [sum.r(), sum.g(), sum.b(), pixel.luminance_sq_sum] {
+                writer.write_all(&c.to_le_bytes())?;
+            }
+            writer.write_all(&pixel.count.to_le_bytes())?;
+        }
+        for sums in self.film.splats() {
+            for sum in sums {
+                writer.write_all(&sum.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(reader: &mut impl Read) -> Result<Checkpoint> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(|_| anyhow!("not a checkpoint file"))?;
+        if &magic != MAGIC {
+            return Err(anyhow!("not a checkpoint file"));
+        }
+
+        let truncated = |_| anyhow!("checkpoint file is truncated");
+        let read_u32 = |reader: &mut dyn Read| -> Result<u32> {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes).map_err(truncated)?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        let read_u64 = |reader: &mut dyn Read| -> Result<u64> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes).map_err(truncated)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let hash = read_u64(reader)?;
+        let seed = read_u64(reader)?;
+        let max_depth = read_u32(reader)?;
+        if max_depth == 0 {
+            return Err(anyhow!("checkpoint has a maximum depth of 0"));
+        }
+        let samples_per_pixel = read_u32(reader)?;
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+
+        // Don't reserve memory up front, since the dimensions of a corrupt file may be arbitrary.
+        let len = width as usize * height as usize;
+        let mut pixels = Vec::new();
+        for _ in 0..len {
+            let r = f32::from_bits(read_u32(reader)?);
+            let g = f32::from_bits(read_u32(reader)?);
+            let b = f32::from_bits(read_u32(reader)?);
+            let luminance_sq_sum = f32::from_bits(read_u32(reader)?);
+            let count = read_u32(reader)?;
+            pixels.push(Pixel { sum: RgbFloat::new(r, g, b), luminance_sq_sum, count });
+        }
+        let mut splats = Vec::new();
+        for _ in 0..len {
+            let mut sums = [0; 4];
+            for sum in sums.iter_mut() {
+                *sum = read_u64(reader)? as i64;
+            }
+            splats.push(sums);
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(anyhow!("unexpected data at the end of the checkpoint file"));
+        }
+
+        let film = Film::from_parts(width, height, pixels, splats);
+        Ok(Checkpoint { film, hash, seed, max_depth, samples_per_pixel })
+    }
+}
+
+// The 64-bit FNV-1a hash, which unlike the standard library's hasher is guaranteed to produce the
+// same values across Rust versions and platforms.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        let mut film = Film::new(3, 2);
+        film.add_sample(1, 0, RgbFloat::new(1.0, 2.0, 3.0));
+        film.add_sample(1, 0, RgbFloat::new(0.5, 0.0, 8.0));
+        film.add_sample(2, 1, RgbFloat::new(0.25, 0.0, 0.0));
+        Checkpoint { film, hash: 42, seed: 7, max_depth: 10, samples_per_pixel: 100 }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join("checkpoint_test_round_trip.ckpt");
+        let saved = checkpoint();
+        saved.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            (loaded.hash, loaded.seed, loaded.max_depth, loaded.samples_per_pixel),
+            (42, 7, 10, 100)
+        );
+        assert_eq!(loaded.film.sample_count(1, 0), 2);
+        assert_eq!(loaded.film.sample_count(0, 0), 0);
+        assert_eq!(loaded.film.image(), saved.film.image());
+        assert_eq!(
+            loaded.film.pixel(1, 0).standard_error(),
+            saved.film.pixel(1, 0).standard_error()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let mut bytes = Vec::new();
+        checkpoint().write(&mut bytes).unwrap();
+        let read = |bytes: &[u8]| Checkpoint::read(&mut &bytes[..]).err().unwrap().to_string();
+        assert_eq!(read(&bytes[..bytes.len() - 1]), "checkpoint file is truncated");
+        assert_eq!(read(b"RTCKPT99"), "not a checkpoint file");
+        assert_eq!(
+            read(&[&bytes[..], &[0]].concat()),
+            "unexpected data at the end of the checkpoint file"
+        );
+
+        let mut bytes = Vec::new();
+        Checkpoint { max_depth: 0, ..checkpoint() }.write(&mut bytes).unwrap();
+        assert_eq!(read(&bytes), "checkpoint has a maximum depth of 0");
+    }
+
+    #[test]
+    fn hash_depends_on_scene_and_settings() {
+        let params = RenderParams::default();
+        let hash = render_hash(b"scene", 4, 3, &params);
+        assert_eq!(hash, render_hash(b"scene", 4, 3, &params));
+        assert_ne!(hash, render_hash(b"other", 4, 3, &params));
+        assert_ne!(hash, render_hash(b"scene", 3, 4, &params));
+        assert_ne!(hash, render_hash(b"scene", 4, 3, &RenderParams { seed: 1, ..params }));
+        assert_ne!(hash, render_hash(b"scene", 4, 3, &RenderParams { max_depth: 2, ..params }));
+        let more_samples = RenderParams { samples_per_pixel: 1000, ..params };
+        assert_eq!(hash, render_hash(b"scene", 4, 3, &more_samples));
+        let stratified = RenderParams { sampler: SamplerType::Stratified, ..params };
+        assert_ne!(hash, render_hash(b"scene", 4, 3, &stratified));
+        let more_samples = RenderParams { samples_per_pixel: 1000, ..stratified };
+        assert_ne!(
+            render_hash(b"scene", 4, 3, &stratified),
+            render_hash(b"scene", 4, 3, &more_samples)
+        );
+    }
+}
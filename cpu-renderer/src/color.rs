@@ -33,8 +33,13 @@ impl RgbFloat {
         RgbFloat(rng.random_vec3())
     }
 
-    pub fn random_in_range(min: f32, max: f32) -> RgbFloat {
-        RgbFloat(Rng::random_vec3_in_range(min.clamp(0.0, 1.0), max.clamp(0.0, 1.0)))
+    pub fn random_in_range(rng: &Rng, min: f32, max: f32) -> RgbFloat {
+        RgbFloat(rng.random_vec3_in_range(min.clamp(0.0, 1.0), max.clamp(0.0, 1.0)))
+    }
+
+    /// The relative luminance of the color, assuming linear sRGB primaries.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
     }
 
     #[inline(always)]
@@ -53,6 +58,26 @@ impl RgbFloat {
     }
 }
 
+/// Encodes a linear color component with the sRGB transfer function, which most displays and
+/// image formats expect.
+pub fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decodes an sRGB-encoded color component into linear light. This is the inverse of
+/// `srgb_encode`.
+pub fn srgb_decode(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 impl convert::From<&RgbFloat> for Rgb<u8> {
     fn from(src: &RgbFloat) -> Self {
         Self([
@@ -69,12 +94,32 @@ impl convert::From<RgbFloat> for Rgb<u8> {
     }
 }
 
+/// Stores the color as is, without clamping, in a floating-point pixel.
+impl convert::From<&RgbFloat> for Rgb<f32> {
+    fn from(src: &RgbFloat) -> Self {
+        Self(src.0.data)
+    }
+}
+
+impl convert::From<RgbFloat> for Rgb<f32> {
+    fn from(src: RgbFloat) -> Self {
+        Self::from(&src)
+    }
+}
+
+impl convert::From<&Rgb<f32>> for RgbFloat {
+    fn from(src: &Rgb<f32>) -> Self {
+        RgbFloat::new(src[0], src[1], src[2])
+    }
+}
+
 impl std::ops::AddAssign for RgbFloat {
     fn add_assign(&mut self, rhs: Self) {
         self.0 += rhs.0
     }
 }
 
+impl_op_ex!(+ |lhs: &RgbFloat, rhs: &RgbFloat| -> RgbFloat { RgbFloat(lhs.0 + rhs.0) });
 impl_op_ex!(* |lhs: &RgbFloat, rhs: &RgbFloat| -> RgbFloat { RgbFloat(lhs.0 * rhs.0) });
 impl_op_ex_commutative!(* |lhs: &RgbFloat, rhs: f32| -> RgbFloat { RgbFloat(lhs.0 * rhs) });
 impl_op_ex!(/ |lhs: &RgbFloat, rhs: f32| -> RgbFloat { RgbFloat(lhs.0 / rhs) });
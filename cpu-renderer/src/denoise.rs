@@ -0,0 +1,295 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{algebra::Vec3, aov::Aovs, color::RgbFloat, film::Film};
+use {image::Rgb32FImage, rayon::prelude::*};
+
+/// An edge-avoiding à-trous wavelet filter, based on "Edge-Avoiding À-Trous Wavelet Transform for
+/// fast Global Illumination Filtering" (Dammertz et al. 2010) and its variance-guided extension in
+/// "Spatiotemporal Variance-Guided Filtering" (Schied et al. 2017).
+///
+/// The filter blurs the noise of a render with increasingly wide kernels, but only between pixels
+/// that see similar surfaces according to the normal and depth AOVs, and whose colors differ by no
+/// more than their noise explains. The lighting is separated from the albedo before filtering, so
+/// that textures stay sharp.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Denoiser {
+    /// The number of filter passes. Each pass doubles the spacing between the taps of the kernel,
+    /// so `n` passes cover a radius of about 2^(n+1) pixels.
+    pub iterations: u32,
+
+    /// How many standard deviations of noise two colors may differ by before they stop being
+    /// blended. Larger values remove more noise but blur more lighting detail.
+    pub color_sigma: f32,
+
+    /// The exponent applied to the cosine between two normals. Larger values preserve more
+    /// geometric detail.
+    pub normal_sigma: f32,
+
+    /// The tolerance for depth differences, relative to the depth change predicted by the slope of
+    /// the surface.
+    pub depth_sigma: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser { iterations: 5, color_sigma: 4.0, normal_sigma: 128.0, depth_sigma: 1.0 }
+    }
+}
+
+// The 1D B3 spline kernel of the à-trous transform.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Albedos darker than this, e.g. of the background, are not divided out of the color.
+const MIN_ALBEDO: f32 = 1e-3;
+
+impl Denoiser {
+    /// Returns the denoised image of `film`. `aovs` must describe the same image, and the pixels
+    /// of the film should have at least 2 samples so that their noise can be estimated. Pixels
+    /// with unknown noise are filtered as if they were very noisy.
+    pub fn apply(&self, film: &Film, aovs: &Aovs) -> Rgb32FImage {
+        let (width, height) = (film.width(), film.height());
+        assert_eq!((aovs.width(), aovs.height()), (width, height), "AOV size mismatch");
+
+        let img = film.image();
+        let mut guides = Vec::with_capacity(img.len() / 3);
+        let mut lighting = Vec::with_capacity(guides.capacity());
+        for (col, row, color) in img.enumerate_pixels() {
+            let aov = aovs.pixel(col, row);
+            let albedo = divisible_albedo(&aov.albedo);
+            let variance = match film.pixel(col, row).standard_error() {
+                Some(error) => {
+                    let luminance = RgbFloat::new(albedo[0], albedo[1], albedo[2]).luminance();
+                    (error / luminance).powi(2)
+                }
+                None => f32::INFINITY,
+            };
+            let normal = match aov.normal.length() {
+                length if length > 0.0 => aov.normal / length,
+                _ => aov.normal,
+            };
+            guides.push(Guide { albedo, normal, depth: aov.depth, depth_slope: [0.0; 2] });
+            lighting.push(Sample { color: [0, 1, 2].map(|c| color[c] / albedo[c]), variance });
+        }
+        compute_depth_slopes(&mut guides, width as usize);
+
+        let frame = Frame { width: width as usize, height: height as usize, guides };
+        for i in 0..self.iterations {
+            lighting = self.filter(&frame, &lighting, 1 << i);
+        }
+
+        Rgb32FImage::from_fn(width, height, |col, row| {
+            let index = row as usize * width as usize + col as usize;
+            let (albedo, color) = (frame.guides[index].albedo, lighting[index].color);
+            image::Rgb([0, 1, 2].map(|c| color[c] * albedo[c]))
+        })
+    }
+
+    // Applies one pass of the filter with the given spacing between taps.
+    fn filter(&self, frame: &Frame, lighting: &[Sample], step: usize) -> Vec<Sample> {
+        let (width, height) = (frame.width, frame.height);
+        let variance = prefilter_variance(lighting, width, height);
+        (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                let (guide, sample) = (&frame.guides[index], &lighting[index]);
+                let luminance = luminance(&sample.color);
+                let color_scale = self.color_sigma * variance[index].sqrt() + 1e-6;
+
+                let (mut color, mut weights, mut variance) = ([0.0; 3], 0.0, 0.0);
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let dy = (j as isize - 2) * step as isize;
+                    let qy = y as isize + dy;
+                    if qy < 0 || qy >= height as isize {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let dx = (i as isize - 2) * step as isize;
+                        let qx = x as isize + dx;
+                        if qx < 0 || qx >= width as isize {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+                        let (other_guide, other) = (&frame.guides[q], &lighting[q]);
+                        let color_difference = (luminance - self::luminance(&other.color)).abs();
+                        let weight = kx
+                            * ky
+                            * self.geometry_weight(guide, other_guide, [dx as f32, dy as f32])
+                            * (-color_difference / color_scale).exp();
+                        if weight.is_nan() || weight <= 0.0 {
+                            continue;
+                        }
+                        for (sum, c) in color.iter_mut().zip(other.color) {
+                            *sum += weight * c;
+                        }
+                        weights += weight;
+                        variance += weight * weight * other.variance;
+                    }
+                }
+                // The center tap always has a positive weight.
+                Sample {
+                    color: color.map(|c| c / weights),
+                    variance: variance / (weights * weights),
+                }
+            })
+            .collect()
+    }
+
+    // The similarity of the surfaces seen by two pixels, which are `offset` pixels apart.
+    fn geometry_weight(&self, a: &Guide, b: &Guide, offset: [f32; 2]) -> f32 {
+        // Pixels that see the background only blend with each other.
+        match (a.depth.is_finite(), b.depth.is_finite()) {
+            (false, false) => return 1.0,
+            (true, true) => {}
+            _ => return 0.0,
+        }
+        let normal = a.normal.dot(&b.normal).max(0.0).powf(self.normal_sigma);
+        let expected = a.depth_slope[0] * offset[0].abs() + a.depth_slope[1] * offset[1].abs();
+        let tolerance = self.depth_sigma * expected + 1e-3 * a.depth;
+        normal * (-(a.depth - b.depth).abs() / tolerance).exp()
+    }
+}
+
+// The per-pixel inputs of the filter that stay the same over all passes.
+struct Guide {
+    albedo: [f32; 3],
+    normal: Vec3,
+    depth: f32,
+
+    // The change in depth per pixel along each axis.
+    depth_slope: [f32; 2],
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    guides: Vec<Guide>,
+}
+
+// The lighting at a pixel, i.e. the color divided by the albedo, and the variance of its luminance.
+#[derive(Clone)]
+struct Sample {
+    color: [f32; 3],
+    variance: f32,
+}
+
+// Returns the channels of the albedo, replacing those that are too dark to divide by with 1.
+fn divisible_albedo(albedo: &RgbFloat) -> [f32; 3] {
+    [albedo.r(), albedo.g(), albedo.b()].map(|c| if c > MIN_ALBEDO { c } else { 1.0 })
+}
+
+fn luminance(color: &[f32; 3]) -> f32 {
+    RgbFloat::new(color[0], color[1], color[2]).luminance()
+}
+
+// Estimates the depth slopes from the differences to neighboring pixels, picking the smaller of
+// the differences on either side so that slopes don't extend across silhouettes.
+fn compute_depth_slopes(guides: &mut [Guide], width: usize) {
+    let height = guides.len() / width.max(1);
+    let slopes: Vec<[f32; 2]> = (0..guides.len())
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let slope = |neighbors: [Option<usize>; 2]| {
+                neighbors
+                    .iter()
+                    .flatten()
+                    .map(|&q| (guides[q].depth - guides[index].depth).abs())
+                    .filter(|d| d.is_finite())
+                    .reduce(f32::min)
+                    .unwrap_or(0.0)
+            };
+            [
+                slope([(x > 0).then(|| index - 1), (x + 1 < width).then(|| index + 1)]),
+                slope([(y > 0).then(|| index - width), (y + 1 < height).then(|| index + width)]),
+            ]
+        })
+        .collect();
+    for (guide, slope) in guides.iter_mut().zip(slopes) {
+        guide.depth_slope = slope;
+    }
+}
+
+// Blurs the variance with a 3x3 Gaussian, which makes the estimates of individual pixels more
+// reliable.
+fn prefilter_variance(lighting: &[Sample], width: usize, height: usize) -> Vec<f32> {
+    const KERNEL: [f32; 3] = [0.25, 0.5, 0.25];
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for (j, ky) in KERNEL.iter().enumerate() {
+                for (i, kx) in KERNEL.iter().enumerate() {
+                    let (qx, qy) = ((x + i).checked_sub(1), (y + j).checked_sub(1));
+                    if let (Some(qx), Some(qy)) = (qx, qy) {
+                        if qx < width && qy < height {
+                            sum += kx * ky * lighting[qy * width + qx].variance;
+                            weights += kx * ky;
+                        }
+                    }
+                }
+            }
+            sum / weights
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aov::AovPixel, random::Rng};
+
+    #[test]
+    fn removes_noise_without_blurring_across_edges() {
+        // Two walls that meet in the middle of the image at a right angle, lit with different
+        // intensities and rendered with noisy samples.
+        let (width, height) = (16, 8);
+        let mut film = Film::new(width, height);
+        let rng = Rng::new(1);
+        let mut pixels = Vec::new();
+        for row in 0..height {
+            for col in 0..width {
+                let (normal, lighting) = if col < width / 2 {
+                    (Vec3::new(1.0, 0.0, 0.0), 0.7)
+                } else {
+                    (Vec3::new(0.0, 0.0, 1.0), 1.0)
+                };
+                for _ in 0..4 {
+                    let noise = 1.0 + (rng.random_float() - 0.5);
+                    film.add_sample(col, row, RgbFloat::gray(0.5 * lighting * noise));
+                }
+                let albedo = RgbFloat::gray(0.5);
+                let pixel = AovPixel { albedo, normal, depth: 10.0, object_id: 1, material_id: 1 };
+                pixels.push((pixel, None));
+            }
+        }
+        let aovs = Aovs::new(width, height, pixels);
+        let denoised = Denoiser::default().apply(&film, &aovs);
+
+        // The noise is reduced, and the columns on either side of the edge keep the brightness of
+        // their wall.
+        let noisy = film.image();
+        let expected = |col: u32| if col < width / 2 { 0.35 } else { 0.5 };
+        let error = |img: &Rgb32FImage| {
+            img.enumerate_pixels()
+                .map(|(col, _, p)| (p.0[0] - expected(col)).abs())
+                .fold(0.0, f32::max)
+        };
+        let mean = |img: &Rgb32FImage, cols: std::ops::Range<u32>| {
+            let n = cols.len() as f32 * height as f32;
+            cols.flat_map(|col| (0..height).map(move |row| (col, row)))
+                .map(|(col, row)| img.get_pixel(col, row).0[0])
+                .sum::<f32>()
+                / n
+        };
+        let column_bias = |col: u32| {
+            let side = if col < width / 2 { 0..width / 2 } else { width / 2..width };
+            (mean(&denoised, col..col + 1) - mean(&noisy, side)).abs()
+        };
+        assert!(error(&denoised) < 0.3 * error(&noisy), "{}", error(&denoised));
+        assert!(column_bias(7) < 0.02 && column_bias(8) < 0.02);
+    }
+}
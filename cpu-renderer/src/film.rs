@@ -0,0 +1,305 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{color::RgbFloat, filter::Filter};
+use {
+    image::Rgb32FImage,
+    rayon::prelude::*,
+    std::sync::atomic::{AtomicI64, Ordering},
+};
+
+/// The samples accumulated for a single pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct Pixel {
+    pub sum: RgbFloat,
+
+    /// The sum of the squared luminance of the samples, used to estimate their variance.
+    pub luminance_sq_sum: f32,
+    pub count: u32,
+}
+
+impl Pixel {
+    pub fn new() -> Pixel {
+        Pixel { sum: RgbFloat::black(), luminance_sq_sum: 0.0, count: 0 }
+    }
+
+    pub fn add_sample(&mut self, value: RgbFloat) {
+        let luminance = value.luminance();
+        self.sum += value;
+        self.luminance_sq_sum += luminance * luminance;
+        self.count += 1;
+    }
+
+    /// Returns the average of the samples, or black if there are none.
+    pub fn mean(&self) -> RgbFloat {
+        match self.count {
+            0 => RgbFloat::black(),
+            count => self.sum / count as f32,
+        }
+    }
+
+    /// Estimates the standard error of the mean luminance of the pixel, i.e. the standard deviation
+    /// of the noise that remains in its value. Returns `None` if there are fewer than 2 samples.
+    pub fn standard_error(&self) -> Option<f32> {
+        if self.count < 2 {
+            return None;
+        }
+        let n = self.count as f32;
+        let mean = self.sum.luminance() / n;
+        let variance = ((self.luminance_sq_sum - n * mean * mean) / (n - 1.0)).max(0.0);
+        Some((variance / n).sqrt())
+    }
+}
+
+impl Default for Pixel {
+    fn default() -> Self {
+        Pixel::new()
+    }
+}
+
+/// Accumulates the radiance samples taken for each pixel of an image. Each pixel of the image is
+/// a weighted average of the samples around it (see `Filter`), so a film can be turned into an
+/// image at any point during a render and more samples can be added later.
+pub struct Film {
+    width: u32,
+    height: u32,
+
+    // The samples taken for each pixel, in row-major order.
+    pixels: Vec<Pixel>,
+
+    // The filtered radiance of each pixel.
+    splats: Splats,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        let len = width as usize * height as usize;
+        Film { width, height, pixels: vec![Pixel::new(); len], splats: Splats::new(width, height) }
+    }
+
+    /// Creates a film from the accumulated samples of each pixel and the fixed-point weighted sums
+    /// of red, green, blue and the weights (see `Splats`), both in row-major order.
+    pub(crate) fn from_parts(
+        width: u32,
+        height: u32,
+        pixels: Vec<Pixel>,
+        splats: Vec<[i64; 4]>,
+    ) -> Film {
+        let len = width as usize * height as usize;
+        assert!(pixels.len() == len && splats.len() == len, "film size mismatch");
+        let sums = splats.into_iter().map(|sums| sums.map(AtomicI64::new)).collect();
+        Film { width, height, pixels, splats: Splats { width, height, sums } }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, col: u32, row: u32) -> &Pixel {
+        &self.pixels[self.index(col, row)]
+    }
+
+    /// Adds a sample to the pixel at the given column and row. The sample only contributes to
+    /// this pixel, as if it were taken at its center with a box filter.
+    pub fn add_sample(&mut self, col: u32, row: u32, value: RgbFloat) {
+        let index = self.index(col, row);
+        self.pixels[index].add_sample(value);
+        self.splats.add(index, &value, 1.0);
+    }
+
+    /// Returns the number of samples taken for the pixel at the given column and row.
+    pub fn sample_count(&self, col: u32, row: u32) -> u32 {
+        self.pixel(col, row).count
+    }
+
+    /// Returns the total number of samples taken for all pixels.
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.count as u64).sum()
+    }
+
+    /// Returns the smallest number of samples taken for any pixel.
+    pub fn min_samples(&self) -> u32 {
+        self.pixels.iter().map(|p| p.count).min().unwrap_or(0)
+    }
+
+    /// Returns the filtered radiance of each pixel. Pixels without any samples are black.
+    /// Negative values, which filters with negative lobes can produce around bright edges, are
+    /// clamped to zero.
+    pub fn image(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.height, |col, row| {
+            let [r, g, b, weight] = self.splats.sums(self.index(col, row));
+            if weight <= 0.0 {
+                return image::Rgb([0.0; 3]);
+            }
+            image::Rgb([r, g, b].map(|c| (c / weight).max(0.0) as f32))
+        })
+    }
+
+    /// Visualizes the number of samples taken for each pixel, from dark blue for the fewest samples
+    /// through cyan and yellow to red for the most.
+    pub fn sample_heatmap(&self) -> Rgb32FImage {
+        const COLORS: [[f32; 3]; 4] =
+            [[0.0, 0.0, 0.5], [0.0, 1.0, 1.0], [1.0, 1.0, 0.0], [1.0, 0.0, 0.0]];
+        let max = self.pixels.iter().map(|p| p.count).max().unwrap_or(0).max(1);
+        Rgb32FImage::from_fn(self.width, self.height, |col, row| {
+            let t = self.sample_count(col, row) as f32 / max as f32 * (COLORS.len() - 1) as f32;
+            let i = (t as usize).min(COLORS.len() - 2);
+            let f = t - i as f32;
+            let (a, b) = (COLORS[i], COLORS[i + 1]);
+            image::Rgb([0, 1, 2].map(|c| (1.0 - f) * a[c] + f * b[c]))
+        })
+    }
+
+    pub(crate) fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// Returns the fixed-point weighted sums of each pixel (see `Splats`).
+    pub(crate) fn splats(&self) -> impl Iterator<Item = [i64; 4]> + '_ {
+        self.splats.sums.iter().map(|sums| sums.each_ref().map(|sum| sum.load(Ordering::Relaxed)))
+    }
+
+    /// Returns the rows of the film with their indices, which can be updated in parallel. Samples
+    /// can be splatted to any pixel from every row.
+    pub(crate) fn par_rows_mut(
+        &mut self,
+    ) -> impl IndexedParallelIterator<Item = (u32, &mut [Pixel], &Splats)> {
+        let width = (self.width as usize).max(1);
+        let splats = &self.splats;
+        self.pixels
+            .par_chunks_mut(width)
+            .enumerate()
+            .map(move |(row, pixels)| (row as u32, pixels, splats))
+    }
+
+    fn index(&self, col: u32, row: u32) -> usize {
+        row as usize * self.width as usize + col as usize
+    }
+}
+
+impl Clone for Film {
+    fn clone(&self) -> Self {
+        Film::from_parts(self.width, self.height, self.pixels.clone(), self.splats().collect())
+    }
+}
+
+/// The filtered radiance of each pixel, which samples can be added to from multiple threads.
+///
+/// The weighted sums are stored in fixed point, so that they don't depend on the order in which
+/// the samples are added. This keeps renders deterministic even though neighboring pixels are
+/// rendered concurrently.
+pub(crate) struct Splats {
+    width: u32,
+    height: u32,
+
+    // The weighted sums of red, green and blue and the sum of the weights for each pixel.
+    sums: Vec<[AtomicI64; 4]>,
+}
+
+impl Splats {
+    // Fixed-point values have 24 fractional bits, which leaves room for sums of about 10^11.
+    const SCALE: f64 = (1 << 24) as f64;
+
+    // Larger radiance values are clamped so that the sums can't overflow.
+    const MAX_VALUE: f64 = 1e6;
+
+    fn new(width: u32, height: u32) -> Splats {
+        let len = width as usize * height as usize;
+        let sums = (0..len).map(|_| [0, 0, 0, 0].map(AtomicI64::new)).collect();
+        Splats { width, height, sums }
+    }
+
+    /// Adds a sample at the continuous position (x, y) in pixel units, where the pixel at column
+    /// `c` and row `r` covers [c, c + 1) x [r, r + 1), to all pixels within the radius of the
+    /// filter.
+    pub(crate) fn splat(&self, x: f32, y: f32, value: &RgbFloat, filter: &Filter) {
+        // The pixels whose centers are within the radius.
+        let range = |p: f32, len: u32| {
+            let first = (p - 0.5 - filter.radius).floor() as i64 + 1;
+            let last = (p - 0.5 + filter.radius).ceil() as i64 - 1;
+            first.max(0)..=last.min(len as i64 - 1)
+        };
+        for row in range(y, self.height) {
+            for col in range(x, self.width) {
+                let weight = filter.weight(x - (col as f32 + 0.5), y - (row as f32 + 0.5));
+                if weight != 0.0 {
+                    self.add(row as usize * self.width as usize + col as usize, value, weight);
+                }
+            }
+        }
+    }
+
+    fn add(&self, index: usize, value: &RgbFloat, weight: f32) {
+        let weight = weight as f64;
+        let values = [
+            value.r() as f64 * weight,
+            value.g() as f64 * weight,
+            value.b() as f64 * weight,
+            weight,
+        ];
+        for (sum, value) in self.sums[index].iter().zip(values) {
+            // NaN samples are dropped by the conversion to zero.
+            let value = value.clamp(-Self::MAX_VALUE, Self::MAX_VALUE);
+            sum.fetch_add((value * Self::SCALE).round() as i64, Ordering::Relaxed);
+        }
+    }
+
+    fn sums(&self, index: usize) -> [f64; 4] {
+        self.sums[index].each_ref().map(|sum| sum.load(Ordering::Relaxed) as f64 / Self::SCALE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn standard_error_of_constant_samples_is_zero() {
+        let mut pixel = Pixel::new();
+        pixel.add_sample(RgbFloat::gray(0.5));
+        assert_eq!(pixel.standard_error(), None);
+        pixel.add_sample(RgbFloat::gray(0.5));
+        pixel.add_sample(RgbFloat::gray(0.5));
+        assert!(pixel.standard_error().unwrap() < 1e-6);
+    }
+
+    #[test]
+    fn standard_error_matches_sample_statistics() {
+        // Samples with luminance 0 and 1 alternating: the sample variance of n samples is
+        // n / (4 (n - 1)), so the standard error of the mean is 1 / (2 sqrt(n - 1)).
+        let mut pixel = Pixel::new();
+        for i in 0..10 {
+            pixel.add_sample(RgbFloat::gray((i % 2) as f32));
+        }
+        let expected = 1.0 / (2.0 * 9f32.sqrt());
+        assert!((pixel.standard_error().unwrap() - expected).abs() < 1e-5);
+        assert!((pixel.mean().r() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn splats_reach_pixels_within_filter_radius() {
+        let mut film = Film::new(5, 4);
+        let filter = Filter::new(FilterKind::Tent);
+        film.par_rows_mut().take(1).for_each(|(_, _, splats)| {
+            splats.splat(2.5, 1.75, &RgbFloat::gray(2.0), &filter);
+        });
+
+        // The tent filter has a radius of 1 pixel, so the sample at the center of column 2
+        // reaches rows 1 and 2, with weights 0.75 and 0.25.
+        let splats: Vec<[i64; 4]> = film.splats().collect();
+        let weight = |col: usize, row: usize| splats[row * 5 + col][3] as f64 / Splats::SCALE;
+        assert_eq!((weight(2, 1), weight(2, 2)), (0.75, 0.25));
+        assert_eq!(splats.iter().filter(|sums| sums[3] != 0).count(), 2);
+
+        let img = film.image();
+        assert_eq!((img.get_pixel(2, 1).0, img.get_pixel(2, 2).0), ([2.0; 3], [2.0; 3]));
+        assert_eq!(img.get_pixel(1, 1).0, [0.0; 3]);
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use std::{f32::consts::PI, str::FromStr};
+
+/// The shape of a reconstruction filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Weighs all samples within the radius equally. With the default radius of half a pixel,
+    /// each pixel is the plain average of its own samples.
+    Box,
+
+    /// Weights fall off linearly with the distance from the pixel center.
+    Tent,
+
+    /// A Gaussian with a standard deviation of a third of the radius, shifted to reach zero at the
+    /// radius.
+    Gaussian,
+
+    /// The cubic filter from "Reconstruction Filters in Computer Graphics" (Mitchell and Netravali
+    /// 1988) with B = C = 1/3, which balances blurring against ringing.
+    Mitchell,
+
+    /// A sinc function windowed by a wider sinc that reaches zero at the radius. The sharpest of
+    /// the filters, at the cost of some ringing around high-contrast edges.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    /// The radius that the filter is usually used with, in pixels.
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err("filter must be 'box', 'tent', 'gaussian', 'mitchell' or 'lanczos'"),
+        }
+    }
+}
+
+/// A reconstruction filter, which determines how much each sample contributes to the pixels around
+/// it. Each pixel is the weighted average of the samples within `radius` pixels of its center,
+/// including those taken for neighboring pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Filter {
+        Filter { kind, radius: kind.default_radius() }
+    }
+
+    /// Returns the weight of a sample at the offset (x, y) from a pixel center, in pixels. The
+    /// filters are separable, i.e. the product of a 1D filter along each axis.
+    pub fn weight(&self, x: f32, y: f32) -> f32 {
+        self.weight_1d(x) * self.weight_1d(y)
+    }
+
+    fn weight_1d(&self, x: f32) -> f32 {
+        let r = self.radius;
+        let x = x.abs();
+        if x >= r {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / r,
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)) / (1.0 - gaussian(r))
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+// The Mitchell-Netravali cubic with B = C = 1/3 over [0, 2], scaled to 1 at the origin.
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    } else {
+        (-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    };
+    value / (6.0 - 2.0 * B)
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    #[test]
+    fn filters_peak_at_center_and_vanish_at_radius() {
+        for kind in KINDS {
+            let filter = Filter::new(kind);
+            assert!((filter.weight(0.0, 0.0) - 1.0).abs() < 1e-5, "{:?}", kind);
+            assert_eq!(filter.weight(filter.radius, 0.0), 0.0, "{:?}", kind);
+            assert_eq!(filter.weight(0.0, -filter.radius - 0.1), 0.0, "{:?}", kind);
+            for x in [0.1, 0.4, 0.9] {
+                assert!(filter.weight(x, 0.0) <= filter.weight(0.0, 0.0), "{:?}", kind);
+                assert_eq!(filter.weight(x, 0.2), filter.weight(-x, -0.2), "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn filters_are_continuous() {
+        for kind in [FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell] {
+            let filter = Filter::new(kind);
+            let near_radius = filter.weight(filter.radius - 1e-3, 0.0);
+            assert!(near_radius.abs() < 1e-2, "{:?}: {}", kind, near_radius);
+        }
+        // The two cubic pieces of the Mitchell filter meet halfway to the radius.
+        let filter = Filter::new(FilterKind::Mitchell);
+        assert!((filter.weight(0.999, 0.0) - filter.weight(1.001, 0.0)).abs() < 1e-2);
+    }
+}
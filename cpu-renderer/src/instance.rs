@@ -0,0 +1,352 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Aabb, Matrix4, Point3, Ray, Vec3},
+    random::Rng,
+    scene::{HitRecord, Hittable},
+};
+use std::sync::Arc;
+
+/// Places a shared object in the scene with an affine transform, such as a translation, a rotation
+/// or a non-uniform scale. The same object can be placed any number of times without copying its
+/// geometry. The transform may change over time, which blurs the object in renders with an open
+/// shutter.
+///
+/// Rays are transformed into the space of the object rather than transforming the object itself.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    motion: Motion,
+    bounds: Option<Aabb>,
+
+    // True if the transform is static and preserves angles (i.e. consists only of rotations,
+    // reflections, translations and uniform scaling), which keeps the densities of sampled light
+    // directions the same in both spaces.
+    preserves_angles: bool,
+}
+
+/// One step of an animated transform. Each step has a value at the start and at the end of the
+/// motion, which are interpolated linearly in between.
+#[derive(Debug, Copy, Clone)]
+pub enum TransformStep {
+    Translate([Vec3; 2]),
+
+    /// The scale factors must not be zero or change sign during the motion.
+    Scale([Vec3; 2]),
+
+    /// Rotates around `axis` by angles in radians. See `Matrix4::rotation`.
+    Rotate {
+        axis: Vec3,
+        angles: [f32; 2],
+    },
+}
+
+enum Motion {
+    Static(Transform),
+
+    /// The steps are applied in order, and move from their start values at `time0` to their end
+    /// values at `time1`.
+    Animated {
+        steps: Vec<TransformStep>,
+        time0: f32,
+        time1: f32,
+    },
+}
+
+// A transform together with its inverse, which maps rays into object space, and the inverse
+// transpose, which maps normals into world space.
+#[derive(Copy, Clone)]
+struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+    normal: Matrix4,
+}
+
+// The number of points in time at which the bounds of an animated instance are evaluated.
+const MOTION_BOUNDS_SAMPLES: usize = 64;
+
+impl Instance {
+    /// Returns `None` if `transform` isn't invertible.
+    pub fn new(object: Arc<dyn Hittable>, transform: Matrix4) -> Option<Instance> {
+        let inverse = transform.inverse()?;
+        let bounds = object.bounding_box().map(|b| b.transformed(&transform));
+
+        // The columns of the linear part must be orthogonal and of the same length.
+        let m = &transform.data;
+        let columns = [0, 1, 2].map(|i| Vec3::new(m[0][i], m[1][i], m[2][i]));
+        let scale = columns[0].length_squared();
+        let preserves_angles = (0..3).all(|i| {
+            (0..3).all(|j| {
+                let expected = if i == j { scale } else { 0.0 };
+                (columns[i].dot(&columns[j]) - expected).abs() <= 1e-5 * scale
+            })
+        });
+        let motion =
+            Motion::Static(Transform { matrix: transform, inverse, normal: inverse.transpose() });
+        Some(Instance { object, motion, bounds, preserves_angles })
+    }
+
+    /// Creates an instance whose transform moves from the start values of `steps` at `time0` to
+    /// their end values at `time1`, and stays at the nearest end outside of this interval. The
+    /// steps are applied in order.
+    ///
+    /// Animated instances are not sampled as light sources, since the time of a ray is not known
+    /// when sampling a light. Steps that don't change are equivalent to `Instance::new`. Returns
+    /// `None` if the transform isn't invertible, i.e. if a scale factor is zero or changes sign.
+    pub fn animated(
+        object: Arc<dyn Hittable>,
+        steps: Vec<TransformStep>,
+        time0: f32,
+        time1: f32,
+    ) -> Option<Instance> {
+        if steps.iter().all(TransformStep::is_static) {
+            return Instance::new(object, evaluate(&steps, 0.0).0);
+        }
+        for step in steps.iter() {
+            if let TransformStep::Scale([start, end]) = step {
+                if !(0..3).all(|i| start.data[i] * end.data[i] > 0.0) {
+                    return None;
+                }
+            }
+        }
+
+        // The bounds are sampled along the motion. Each sample is padded by the distance that the
+        // corners of the object move until the next sample, which also covers the curved paths of
+        // rotating corners.
+        let bounds = object.bounding_box().map(|b| {
+            let corners = |t: f32| {
+                let (matrix, _) = evaluate(&steps, t);
+                b.corners().map(|c| matrix.transform_point(&c))
+            };
+            let mut previous = corners(0.0);
+            let mut bounds = Aabb::new(&previous[0], &previous[0]);
+            for i in 1..=MOTION_BOUNDS_SAMPLES {
+                let current = corners(i as f32 / MOTION_BOUNDS_SAMPLES as f32);
+                let distance = previous
+                    .iter()
+                    .zip(current.iter())
+                    .map(|(a, b)| (b - a).length())
+                    .fold(0.0, f32::max);
+                let padding = Vec3::new(distance, distance, distance);
+                for corner in previous.iter().chain(current.iter()) {
+                    bounds = bounds.union(&Aabb::new(&(corner - padding), &(corner + padding)));
+                }
+                previous = current;
+            }
+            bounds
+        });
+        let motion = Motion::Animated { steps, time0, time1 };
+        Some(Instance { object, motion, bounds, preserves_angles: false })
+    }
+
+    fn transform_at(&self, time: f32) -> Transform {
+        match &self.motion {
+            Motion::Static(transform) => *transform,
+            Motion::Animated { steps, time0, time1 } => {
+                let t = if time1 > time0 {
+                    ((time - time0) / (time1 - time0)).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let (matrix, inverse) = evaluate(steps, t);
+                Transform { matrix, inverse, normal: inverse.transpose() }
+            }
+        }
+    }
+}
+
+impl TransformStep {
+    fn is_static(&self) -> bool {
+        match self {
+            TransformStep::Translate([start, end]) | TransformStep::Scale([start, end]) => {
+                start.data == end.data
+            }
+            TransformStep::Rotate { angles: [start, end], .. } => start == end,
+        }
+    }
+
+    // Returns the transform of the step at `t` between 0 and 1, and its inverse.
+    fn at(&self, t: f32) -> (Matrix4, Matrix4) {
+        match self {
+            TransformStep::Translate([start, end]) => {
+                let offset = start + t * (end - start);
+                (Matrix4::translation(&offset), Matrix4::translation(&-offset))
+            }
+            TransformStep::Scale([start, end]) => {
+                let factors = start + t * (end - start);
+                let inverse = Vec3::new(1.0 / factors.x(), 1.0 / factors.y(), 1.0 / factors.z());
+                (Matrix4::scaling(&factors), Matrix4::scaling(&inverse))
+            }
+            TransformStep::Rotate { axis, angles: [start, end] } => {
+                let angle = start + t * (end - start);
+                (Matrix4::rotation(axis, angle), Matrix4::rotation(axis, -angle))
+            }
+        }
+    }
+}
+
+// Composes the steps at `t` between 0 and 1. Returns the transform and its inverse.
+fn evaluate(steps: &[TransformStep], t: f32) -> (Matrix4, Matrix4) {
+    steps.iter().fold((Matrix4::identity(), Matrix4::identity()), |(matrix, inverse), step| {
+        let (step, step_inverse) = step.at(t);
+        (step * matrix, inverse * step_inverse)
+    })
+}
+
+impl Hittable for Instance {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let transform = self.transform_at(ray.time);
+
+        // The direction is not normalized, so distances along the ray are the same in both spaces.
+        let local_ray = Ray {
+            origin: transform.inverse.transform_point(&ray.origin),
+            direction: transform.inverse.transform_vector(&ray.direction),
+            time: ray.time,
+        };
+        let hit = self.object.bounded_hit(&local_ray, t_min, t_max)?;
+
+        // Normals are transformed by the inverse transpose so that they stay perpendicular to the
+        // surface. The normal still faces the ray since the transform preserves the sign of the
+        // dot product between the normal and the ray direction.
+        let normal = transform.normal.transform_vector(&hit.normal).normalized();
+        Some(HitRecord { point: ray.at(hit.t), normal, ..hit })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.object.is_emissive()
+    }
+
+    /// Only static instances whose transform preserves angles can be sampled. Others are still lit
+    /// by rays that hit them at random.
+    fn can_sample(&self) -> bool {
+        matches!(self.motion, Motion::Static(_))
+            && self.preserves_angles
+            && self.object.can_sample()
+    }
+
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        let transform = match (&self.motion, self.preserves_angles) {
+            (Motion::Static(transform), true) => transform,
+            _ => return None,
+        };
+        let local =
+            self.object.sample_direction(&transform.inverse.transform_point(origin), rng)?;
+        Some(transform.matrix.transform_vector(&local))
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let transform = match (&self.motion, self.preserves_angles) {
+            (Motion::Static(transform), true) => transform,
+            _ => return 0.0,
+        };
+        let origin = transform.inverse.transform_point(origin);
+        self.object.direction_pdf(&origin, &transform.inverse.transform_vector(direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::RgbFloat, material::Lambertian, scene::Sphere};
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere {
+            center: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Lambertian::new(RgbFloat::gray(0.5)),
+        })
+    }
+
+    #[test]
+    fn hits_transformed_object() {
+        // An ellipsoid with radii 2, 1 and 1, centered at (0, 0, -5).
+        let transform = Matrix4::translation(&Vec3::new(0.0, 0.0, -5.0))
+            * Matrix4::scaling(&Vec3::new(2.0, 1.0, 1.0));
+        let instance = Instance::new(unit_sphere(), transform).unwrap();
+
+        let ray = |x: f32, y: f32| Ray {
+            origin: Point3::new(0.0, 0.0, 0.0),
+            direction: Vec3::new(x, y, -5.0),
+            time: 0.0,
+        };
+        assert!(instance.hit(&ray(1.9, 0.0)).is_some());
+        assert!(instance.hit(&ray(0.0, 1.1)).is_none());
+
+        // Along the x axis the ellipsoid is hit at (2, 0, -5), where its normal points along +x.
+        let side = Ray {
+            origin: Point3::new(10.0, 0.0, -5.0),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hit = instance.hit(&side).unwrap();
+        assert!((hit.t - 8.0).abs() < 1e-4 && (hit.point.x() - 2.0).abs() < 1e-4);
+        assert!((hit.normal.x() - 1.0).abs() < 1e-5 && hit.is_front_face);
+
+        // At 45 degrees around the z axis, the normal of the ellipsoid is not radial.
+        let offset = Vec3::new(2.0 * 0.5f32.sqrt(), 0.5f32.sqrt(), 0.0);
+        let center = Point3::new(0.0, 0.0, -5.0);
+        let hit = instance
+            .hit(&Ray { origin: center + 3.0 * offset, direction: -offset, time: 0.0 })
+            .unwrap();
+        assert!((hit.point - (center + offset)).length() < 1e-4);
+        let expected = Vec3::new(0.5f32.sqrt() / 2.0, 0.5f32.sqrt(), 0.0).normalized();
+        assert!((hit.normal - expected).length() < 1e-4, "{:?}", hit.normal);
+
+        let bounds = instance.bounding_box().unwrap();
+        assert!((bounds.min.x() + 2.0).abs() < 1e-5 && (bounds.max.z() + 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn samples_lights_through_rigid_transforms() {
+        let transform = Matrix4::translation(&Vec3::new(0.0, 3.0, 0.0))
+            * Matrix4::rotation(&Vec3::new(1.0, 0.0, 0.0), 1.0)
+            * Matrix4::scaling(&Vec3::new(0.5, 0.5, 0.5));
+        let instance = Instance::new(unit_sphere(), transform).unwrap();
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let direction = instance.sample_direction(&origin, &Rng::new(3)).unwrap();
+        assert!(instance.hit(&Ray { origin, direction, time: 0.0 }).is_some());
+
+        // The sphere of radius 0.5 at a distance of 3 covers a cone with cos(theta) = sqrt(35) / 6.
+        let cos_theta_max = 35f32.sqrt() / 6.0;
+        let expected = 1.0 / (2.0 * std::f32::consts::PI * (1.0 - cos_theta_max));
+        let pdf = instance.direction_pdf(&origin, &Vec3::new(0.0, 1.0, 0.0));
+        assert!((pdf - expected).abs() < 1e-3 * expected, "{} {}", pdf, expected);
+
+        let squashed =
+            Instance::new(unit_sphere(), Matrix4::scaling(&Vec3::new(1.0, 2.0, 1.0))).unwrap();
+        let origin = Point3::new(0.0, 5.0, 0.0);
+        assert!(squashed.sample_direction(&origin, &Rng::new(3)).is_none());
+    }
+
+    #[test]
+    fn animated_instances_move_with_ray_time() {
+        // A sphere that orbits half way around the z axis at a distance of 2 between times 1 and 3.
+        let steps = vec![
+            TransformStep::Translate([Vec3::new(2.0, 0.0, 0.0); 2]),
+            TransformStep::Rotate {
+                axis: Vec3::new(0.0, 0.0, 1.0),
+                angles: [0.0, std::f32::consts::PI],
+            },
+        ];
+        let instance = Instance::animated(unit_sphere(), steps, 1.0, 3.0).unwrap();
+        let hits = |x: f32, y: f32, time: f32| {
+            let ray =
+                Ray { origin: Point3::new(x, y, 10.0), direction: Vec3::new(0.0, 0.0, -1.0), time };
+            instance.hit(&ray).is_some()
+        };
+        assert!(hits(2.0, 0.0, 1.0) && !hits(2.0, 0.0, 2.0));
+        assert!(hits(0.0, 2.0, 2.0) && hits(-2.0, 0.0, 3.0));
+        assert!(hits(2.0, 0.0, 0.0) && hits(-2.0, 0.0, 5.0));
+
+        let bounds = instance.bounding_box().unwrap();
+        assert!(bounds.min.x() <= -3.0 && bounds.max.x() >= 3.0);
+        assert!(bounds.min.y() <= -1.0 && (3.0..3.5).contains(&bounds.max.y()));
+        assert!(instance.sample_direction(&Point3::new(0.0, 0.0, 5.0), &Rng::new(3)).is_none());
+    }
+}
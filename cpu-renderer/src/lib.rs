@@ -0,0 +1,40 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+//! A CPU path tracer based on the _Ray Tracing in One Weekend_ series.
+//!
+//! Scenes are lists of [`scene::Hittable`] objects, built by hand, with one of the built-in
+//! [`scenes`], or loaded with [`scene_file::load_scene_file`]. A [`render::Renderer`] renders them
+//! into a framebuffer of linear radiance, which [`output::save_image`] writes to disk.
+
+#[macro_use]
+extern crate impl_ops;
+
+pub mod algebra;
+pub mod aov;
+mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod instance;
+mod light;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod obj;
+pub mod output;
+pub mod planar;
+pub mod quadric;
+pub mod random;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod scenes;
+pub mod texture;
+pub mod tonemap;
@@ -0,0 +1,210 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Point3, Vec3},
+    random::Rng,
+    scene::{Hittable, Scene},
+};
+use std::sync::Arc;
+
+/// The emissive objects of a scene, which are sampled explicitly to estimate direct lighting.
+pub struct LightList {
+    lights: Vec<Arc<dyn Hittable>>,
+}
+
+impl LightList {
+    /// Collects all emissive top-level objects of `scene` that can be sampled. The lights remain
+    /// part of the returned scene and are shared with the returned list. Emissive objects that
+    /// can't be sampled are left out, since a light sample that picks them would be wasted.
+    pub fn extract(scene: Scene) -> (Scene, LightList) {
+        let mut lights: Vec<Arc<dyn Hittable>> = Vec::new();
+        let scene = scene
+            .into_iter()
+            .map(|object| {
+                if !object.is_emissive() || !object.can_sample() {
+                    return object;
+                }
+                let light: Arc<dyn Hittable> = Arc::from(object);
+                lights.push(light.clone());
+                Box::new(light) as Box<dyn Hittable>
+            })
+            .collect();
+        (scene, LightList { lights })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Picks one of the lights uniformly at random and samples a direction from `origin` towards
+    /// it.
+    pub fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index =
+            ((rng.random_float() * self.lights.len() as f32) as usize).min(self.lights.len() - 1);
+        self.lights[index].sample_direction(origin, rng)
+    }
+
+    /// Returns the probability density with which `sample_direction` generates `direction`. This is
+    /// the average of the densities of the individual lights since each light is equally likely to
+    /// be picked.
+    pub fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self.lights.iter().map(|l| l.direction_pdf(origin, direction)).sum();
+        sum / self.lights.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algebra::Ray,
+        color::RgbFloat,
+        material::{DiffuseLight, Lambertian},
+        mesh::{MeshData, TriangleMesh},
+        planar::{BoxShape, Disk, Plane, Quad},
+        quadric::Torus,
+        scene::Sphere,
+    };
+
+    // Estimates the solid angle that `object` subtends from `origin` by sampling the unit sphere
+    // uniformly, and compares it to the expected value of 1 / pdf over the object's own samples.
+    fn check_pdf(object: &dyn Hittable, origin: &Point3) {
+        let rng = Rng::new(0);
+        let count = 100000;
+        let hits = (0..count)
+            .filter(|_| {
+                object
+                    .hit(&Ray { origin: *origin, direction: rng.sample_sphere(), time: 0.0 })
+                    .is_some()
+            })
+            .count();
+        let expected = 4.0 * std::f32::consts::PI * hits as f32 / count as f32;
+
+        let mut estimate = 0.0;
+        for _ in 0..count {
+            let direction = object.sample_direction(origin, &rng).unwrap();
+            let pdf = object.direction_pdf(origin, &direction);
+            assert!(pdf > 0.0);
+            estimate += 1.0 / pdf;
+        }
+        estimate /= count as f32;
+        assert!((estimate - expected).abs() < 0.05 * expected, "{} != {}", estimate, expected);
+    }
+
+    #[test]
+    fn sphere_pdf_matches_solid_angle() {
+        let sphere = Sphere {
+            center: Point3::new(0.0, 0.0, -3.0),
+            radius: 1.0,
+            material: DiffuseLight::new(RgbFloat::white()),
+        };
+        check_pdf(&sphere, &Point3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mesh_pdf_matches_solid_angle() {
+        let data = MeshData {
+            positions: vec![
+                Point3::new(-1.0, 1.0, -1.0),
+                Point3::new(1.0, 1.0, -1.0),
+                Point3::new(1.0, 1.0, 1.0),
+                Point3::new(-1.0, 1.0, 1.0),
+                Point3::new(3.0, 2.0, 0.0),
+            ],
+            indices: vec![[0, 1, 2], [0, 2, 3], [1, 4, 2]],
+            ..Default::default()
+        };
+        let mesh = TriangleMesh::new(data, DiffuseLight::new(RgbFloat::white())).unwrap();
+        check_pdf(&mesh, &Point3::new(0.2, 0.0, 0.3));
+
+        // A closed, smooth-shaded octahedron, which directions pass through twice.
+        let positions = vec![
+            Point3::new(1.0, 0.0, -3.0),
+            Point3::new(-1.0, 0.0, -3.0),
+            Point3::new(0.0, 1.0, -3.0),
+            Point3::new(0.0, -1.0, -3.0),
+            Point3::new(0.0, 0.0, -2.0),
+            Point3::new(0.0, 0.0, -4.0),
+        ];
+        let center = Point3::new(0.0, 0.0, -3.0);
+        let normals = positions.iter().map(|p| p - center).collect();
+        let data = MeshData {
+            positions,
+            normals: Some(normals),
+            indices: vec![
+                [0, 2, 4],
+                [2, 1, 4],
+                [1, 3, 4],
+                [3, 0, 4],
+                [2, 0, 5],
+                [1, 2, 5],
+                [3, 1, 5],
+                [0, 3, 5],
+            ],
+            ..Default::default()
+        };
+        let mesh = TriangleMesh::new(data, DiffuseLight::new(RgbFloat::white())).unwrap();
+        check_pdf(&mesh, &Point3::new(0.5, 0.3, 0.0));
+    }
+
+    #[test]
+    fn planar_pdfs_match_solid_angle() {
+        let light = DiffuseLight::new(RgbFloat::white());
+        let quad = Quad::new(
+            Point3::new(-1.0, 1.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.5),
+            Vec3::new(0.0, 0.5, 2.0),
+            light.clone(),
+        );
+        check_pdf(&quad, &Point3::new(0.2, 0.0, 0.3));
+        let disk =
+            Disk::new(Point3::new(0.0, 2.0, -1.0), Vec3::new(1.0, -1.0, 0.0), 1.5, light.clone());
+        check_pdf(&disk, &Point3::new(0.2, 0.0, 0.3));
+
+        // Directions towards a box pass through two faces.
+        let shape =
+            BoxShape::new(&Point3::new(-1.0, 1.0, -2.0), &Point3::new(2.0, 1.5, 0.0), light);
+        check_pdf(&shape, &Point3::new(0.2, 0.0, 0.3));
+    }
+
+    #[test]
+    fn extracts_emissive_objects() {
+        let sphere = |material| {
+            Box::new(Sphere { center: Point3::new(0.0, 0.0, 0.0), radius: 1.0, material })
+        };
+        let scene: Scene = vec![
+            sphere(Lambertian::new(RgbFloat::white())),
+            sphere(DiffuseLight::new(RgbFloat::white())),
+        ];
+        let (scene, lights) = LightList::extract(scene);
+        assert_eq!(scene.len(), 2);
+        assert_eq!(lights.lights.len(), 1);
+        assert!(lights.lights[0].is_emissive());
+    }
+
+    #[test]
+    fn skips_lights_that_cannot_be_sampled() {
+        let light = DiffuseLight::new(RgbFloat::white());
+        let scene: Scene = vec![
+            Box::new(Plane::new(
+                Point3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                light.clone(),
+            )),
+            Box::new(Torus { major_radius: 1.0, minor_radius: 0.2, material: light }),
+        ];
+        let (scene, lights) = LightList::extract(scene);
+        assert_eq!(scene.len(), 2);
+        assert!(scene.iter().all(|object| object.is_emissive()));
+        assert!(lights.is_empty());
+    }
+}
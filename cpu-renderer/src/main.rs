@@ -3,28 +3,32 @@
 // Use of this source code is governed by the MIT License described
 // in the LICENSE file.
 
-#[macro_use]
-extern crate impl_ops;
-
 use {
-    anyhow::{anyhow, Context, Result},
+    anyhow::{anyhow, Result},
     argh::FromArgs,
-    image::RgbImage,
-    std::str::FromStr,
-};
-
-mod algebra;
-mod camera;
-mod color;
-mod material;
-mod random;
-mod render;
-mod scene;
-mod scenes;
-
-use crate::{
-    random::Rng,
-    scenes::{cover_scene, simple_scene},
+    cpu_renderer::{
+        aov::Aov,
+        camera::{Camera, CameraParams},
+        checkpoint::{render_hash, Checkpoint},
+        denoise::Denoiser,
+        film::Film,
+        filter::{Filter, FilterKind},
+        output::{save_image, OutputFormat, SaveOptions},
+        random::Rng,
+        render::{AdaptiveSampling, CancellationToken, Progress, RenderParams, Renderer},
+        sampler::SamplerType,
+        scene_file::{load_scene_file, RenderSettings},
+        scenes::{cornell_box, cover_scene, simple_scene},
+        tonemap::{ToneMapper, ToneMapping},
+    },
+    image::Rgb32FImage,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
 };
 
 // Defaults.
@@ -33,10 +37,27 @@ const MAX_DEPTH: u32 = 50;
 const HEIGHT: u32 = 675;
 const ASPECT_RATIO: f32 = 16.0 / 9.0;
 const WIDTH: u32 = (ASPECT_RATIO * HEIGHT as f32) as u32;
+const OUTPUT: &str = "image.ppm";
+const JPEG_QUALITY: u8 = 90;
+const SAMPLES_PER_PASS: u32 = 1;
+const MIN_SAMPLES: u32 = 16;
+
+const PROGRESS_BAR_WIDTH: usize = 40;
 
 enum SceneType {
     Simple,
     Cover,
+    Cornell,
+}
+
+impl SceneType {
+    fn name(&self) -> &'static str {
+        match self {
+            SceneType::Simple => "simple",
+            SceneType::Cover => "cover",
+            SceneType::Cornell => "cornell",
+        }
+    }
 }
 
 impl FromStr for SceneType {
@@ -46,7 +67,27 @@ impl FromStr for SceneType {
         match src {
             "simple" => Ok(SceneType::Simple),
             "cover" => Ok(SceneType::Cover),
-            _ => Err("scene must be 'simple' or 'cover'"),
+            "cornell" => Ok(SceneType::Cornell),
+            _ => Err("scene must be 'simple', 'cover' or 'cornell'"),
+        }
+    }
+}
+
+// An auxiliary output and the path of the file that it is saved to, given as "<name>=<path>".
+struct AovOutput {
+    aov: Aov,
+    path: PathBuf,
+}
+
+impl FromStr for AovOutput {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src.split_once('=') {
+            Some((aov, path)) if !path.is_empty() => {
+                Ok(AovOutput { aov: aov.parse()?, path: PathBuf::from(path) })
+            }
+            _ => Err("AOV outputs must be given as <name>=<path>, e.g. albedo=albedo.exr"),
         }
     }
 }
@@ -55,16 +96,20 @@ impl FromStr for SceneType {
 #[derive(FromArgs)]
 struct Args {
     /// number of samples per pixel
-    #[argh(option, short = 'p', default = "SAMPLES_PER_PIXEL")]
-    samples_per_pixel: u32,
+    #[argh(option, short = 'p')]
+    samples_per_pixel: Option<u32>,
 
     /// maximum number of ray bounces per traced path
-    #[argh(option, short = 'm', default = "MAX_DEPTH - 1")]
-    max_bounces: u32,
+    #[argh(option, short = 'm')]
+    max_bounces: Option<u32>,
+
+    /// the scene to render ("simple", "cover" or "cornell")
+    #[argh(option, short = 's')]
+    scene: Option<SceneType>,
 
-    /// the scene to render ("simple" or "cover")
-    #[argh(option, short = 's', default = "SceneType::Cover")]
-    scene: SceneType,
+    /// path to a JSON scene file to render instead of a built-in scene
+    #[argh(option)]
+    scene_file: Option<PathBuf>,
 
     /// image width
     #[argh(option, short = 'w')]
@@ -73,42 +118,394 @@ struct Args {
     /// image height
     #[argh(option, short = 'h')]
     height: Option<u32>,
+
+    /// output image path; the extension selects the format (.ppm, .png, .jpg, .tga, .bmp, .exr
+    /// or .hdr)
+    #[argh(option, short = 'o', default = "PathBuf::from(OUTPUT)")]
+    output: PathBuf,
+
+    /// also save the linear radiance to an OpenEXR (.exr) or Radiance HDR (.hdr) file
+    #[argh(option)]
+    hdr_output: Option<PathBuf>,
+
+    /// also save an auxiliary output to an OpenEXR file, given as <name>=<path>; the name is
+    /// "albedo", "normal", "depth", "object-id" or "material-id" (can be repeated)
+    #[argh(option)]
+    aov: Vec<AovOutput>,
+
+    /// remove noise from the final image with a filter guided by the albedo, normal and depth of
+    /// the visible surfaces
+    #[argh(switch)]
+    denoise: bool,
+
+    /// JPEG quality between 1 and 100
+    #[argh(option, default = "JPEG_QUALITY")]
+    jpeg_quality: u8,
+
+    /// how radiance is mapped to the displayable range in 8-bit outputs ("clamp", "reinhard",
+    /// "extended-reinhard", "aces" or "agx")
+    #[argh(option, default = "ToneMapper::Clamp")]
+    tone_map: ToneMapper,
+
+    /// exposure adjustment in stops, applied before tone mapping
+    #[argh(option, default = "0.0")]
+    exposure: f32,
+
+    /// radiance that the extended Reinhard tone mapper maps to white; defaults to the brightest
+    /// pixel
+    #[argh(option)]
+    white_point: Option<f32>,
+
+    /// overwrite existing output files
+    #[argh(switch)]
+    force: bool,
+
+    /// seed for all random numbers; renders with the same seed and options are identical
+    #[argh(option, default = "0")]
+    seed: u64,
+
+    /// how the random numbers of each sample are generated ("independent", "stratified", "halton",
+    /// "sobol" or "blue-noise"); the others spread the samples of a pixel more evenly than
+    /// "independent" to reduce noise, and "blue-noise" also turns the remaining noise into a fine
+    /// grain that is less visible
+    #[argh(option, default = "SamplerType::Independent")]
+    sampler: SamplerType,
+
+    /// reconstruction filter that weighs the samples around each pixel ("box", "tent",
+    /// "gaussian", "mitchell" or "lanczos"); wider filters reduce aliasing along edges
+    #[argh(option, default = "FilterKind::Box")]
+    filter: FilterKind,
+
+    /// radius of the reconstruction filter in pixels; defaults to 0.5 for the box filter, 1 for
+    /// tent, 1.5 for gaussian, 2 for mitchell and 3 for lanczos
+    #[argh(option)]
+    filter_radius: Option<f32>,
+
+    /// number of samples added to every pixel in each pass over the image
+    #[argh(option, default = "SAMPLES_PER_PASS")]
+    samples_per_pass: u32,
+
+    /// write the current image to the output files every N passes
+    #[argh(option)]
+    checkpoint_passes: Option<u32>,
+
+    /// write the current image to the output files after the first pass that ends at least N
+    /// seconds after the last write
+    #[argh(option)]
+    checkpoint_interval: Option<u64>,
+
+    /// also save the accumulated samples to this file at every checkpoint and at the end of the
+    /// render, so that the render can be continued with --resume
+    #[argh(option)]
+    checkpoint_file: Option<PathBuf>,
+
+    /// stop sampling a pixel once the standard error of its luminance is below this fraction of
+    /// the luminance (e.g. 0.01); the number of samples per pixel becomes the maximum
+    #[argh(option)]
+    noise_threshold: Option<f32>,
+
+    /// number of samples that every pixel receives before it can stop early with --noise-threshold
+    #[argh(option, default = "MIN_SAMPLES")]
+    min_samples: u32,
+
+    /// save an image of the number of samples taken for each pixel to this file
+    #[argh(option)]
+    sample_heatmap: Option<PathBuf>,
+
+    /// continue the render saved in this checkpoint file, which is updated as the render
+    /// progresses unless --checkpoint-file is given; existing output files are overwritten
+    #[argh(option)]
+    resume: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
     let args: Args = argh::from_env();
 
-    // Image
-    let (width, height, aspect_ratio) = match (args.width, args.height) {
-        (None, None) => (WIDTH, HEIGHT, ASPECT_RATIO),
-        (Some(w), None) => (w, ((w as f32) / ASPECT_RATIO) as u32, ASPECT_RATIO),
-        (None, Some(h)) => (((h as f32) * ASPECT_RATIO) as u32, h, ASPECT_RATIO),
-        (Some(w), Some(h)) => (w, h, (w as f32) / (h as f32)),
+    // Output. Check the output paths before rendering so that mistakes aren't discovered only
+    // after a long render.
+    let mut outputs = vec![args.output.clone()];
+    if let Some(path) = &args.hdr_output {
+        if !OutputFormat::from_path(path)?.is_hdr() {
+            return Err(anyhow!("--hdr-output must have an .exr or .hdr extension"));
+        }
+        outputs.push(path.clone());
+    }
+    let checkpoint_path = args.checkpoint_file.clone().or_else(|| args.resume.clone());
+    if let Some(path) = &args.checkpoint_file {
+        if !args.force && args.resume.as_ref() != Some(path) && path.exists() {
+            let path = path.display();
+            return Err(anyhow!("\"{}\" already exists, use --force to overwrite it", path));
+        }
+    }
+    for output in args.aov.iter() {
+        if OutputFormat::from_path(&output.path)? != OutputFormat::OpenExr {
+            return Err(anyhow!("AOV outputs must have an .exr extension"));
+        }
+    }
+    let aov_paths = args.aov.iter().map(|output| &output.path);
+    let mut output_paths = Vec::new();
+    for path in outputs.iter().chain(&args.sample_heatmap).chain(aov_paths) {
+        OutputFormat::from_path(path)?;
+        if output_paths.contains(&path) || checkpoint_path.as_ref() == Some(path) {
+            return Err(anyhow!("\"{}\" is given for more than one output", path.display()));
+        }
+        output_paths.push(path);
+        if !args.force && args.resume.is_none() && path.exists() {
+            let path = path.display();
+            return Err(anyhow!("\"{}\" already exists, use --force to overwrite it", path));
+        }
+    }
+    if !(1..=100).contains(&args.jpeg_quality) {
+        return Err(anyhow!("JPEG quality must be between 1 and 100"));
+    }
+    if args.samples_per_pass == 0 || args.checkpoint_passes == Some(0) {
+        return Err(anyhow!("pass counts must be non-zero"));
+    }
+    if args.noise_threshold.is_some_and(|t| t.is_nan() || t <= 0.0) {
+        return Err(anyhow!("the noise threshold must be positive"));
+    }
+    let filter = Filter {
+        kind: args.filter,
+        radius: args.filter_radius.unwrap_or_else(|| args.filter.default_radius()),
     };
+    if filter.radius.is_nan() || filter.radius < 0.5 {
+        return Err(anyhow!("the filter radius must be at least 0.5 pixels"));
+    }
+    if !args.exposure.is_finite() {
+        return Err(anyhow!("the exposure must be a finite number of stops"));
+    }
+    if args.white_point.is_some_and(|w| w.is_nan() || w <= 0.0) {
+        return Err(anyhow!("the white point must be positive"));
+    }
+    let tone_mapping = ToneMapping {
+        tone_mapper: args.tone_map,
+        exposure: args.exposure,
+        white_point: args.white_point,
+    };
+    let overwrite = args.force || args.resume.is_some();
+    let writer = Arc::new(ImageWriter {
+        outputs,
+        options: Mutex::new(SaveOptions {
+            jpeg_quality: args.jpeg_quality,
+            overwrite,
+            tone_mapping,
+        }),
+    });
+
+    // Random scene content, such as the layout of the cover scene and procedural textures, is drawn
+    // from its own stream so that it doesn't depend on the render settings.
+    let scene_rng = Rng::new(args.seed);
+
+    // Scene file. Options given on the command line take precedence over the render settings in
+    // the file.
+    let scene_file = match (&args.scene_file, &args.scene) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow!("--scene and --scene-file are mutually exclusive"))
+        }
+        (Some(path), None) => Some(load_scene_file(path, &scene_rng)?),
+        (None, _) => None,
+    };
+    let settings = scene_file.as_ref().map_or(RenderSettings::default(), |f| f.settings);
+    let samples_per_pixel =
+        args.samples_per_pixel.or(settings.samples_per_pixel).unwrap_or(SAMPLES_PER_PIXEL);
+    let max_bounces = args.max_bounces.or(settings.max_bounces).unwrap_or(MAX_DEPTH - 1);
+
+    // Image
+    let (width, height, aspect_ratio) =
+        match (args.width.or(settings.width), args.height.or(settings.height)) {
+            (None, None) => (WIDTH, HEIGHT, ASPECT_RATIO),
+            (Some(w), None) => (w, ((w as f32) / ASPECT_RATIO) as u32, ASPECT_RATIO),
+            (None, Some(h)) => (((h as f32) * ASPECT_RATIO) as u32, h, ASPECT_RATIO),
+            (Some(w), Some(h)) => (w, h, (w as f32) / (h as f32)),
+        };
     if width == 0 || height == 0 {
         return Err(anyhow!("image dimensions must be non-zero"));
     }
 
-    // Scene
-    let rng = Rng::new();
-    let (scene, camera) = match args.scene {
-        SceneType::Simple => simple_scene(aspect_ratio),
-        SceneType::Cover => cover_scene(&rng, aspect_ratio),
+    // Scene. `scene_id` identifies the scene in checkpoints. The files referenced by a scene file
+    // aren't included, so changes to them go unnoticed by `--resume`.
+    let scene_id = match (&args.scene_file, &args.scene) {
+        (Some(path), _) => fs::read(path)?,
+        (None, scene) => scene.as_ref().unwrap_or(&SceneType::Cover).name().as_bytes().to_vec(),
+    };
+    let fog = scene_file.as_ref().and_then(|file| file.fog.clone());
+    let mut media = Vec::new();
+    let (scene, camera, background) = match scene_file {
+        Some(file) => {
+            media = file.media;
+            (file.scene, Camera::new(CameraParams { aspect_ratio, ..file.camera }), file.background)
+        }
+        None => match args.scene.as_ref().unwrap_or(&SceneType::Cover) {
+            SceneType::Simple => simple_scene(aspect_ratio),
+            SceneType::Cover => cover_scene(&scene_rng, aspect_ratio),
+            SceneType::Cornell => cornell_box(aspect_ratio),
+        },
     };
 
     // Render
-    let mut img = RgbImage::new(width, height);
-    render::render_scene(
-        &scene,
-        &camera,
-        &rng,
-        args.max_bounces + 1,
-        args.samples_per_pixel,
-        &mut img,
+    let params = RenderParams {
+        max_depth: max_bounces + 1,
+        samples_per_pixel,
+        samples_per_pass: args.samples_per_pass,
+        seed: args.seed,
+        sampler: args.sampler,
+        filter,
+        adaptive: args.noise_threshold.map(|noise_threshold| AdaptiveSampling {
+            noise_threshold,
+            min_samples: args.min_samples,
+        }),
+    };
+    let hash = render_hash(&scene_id, width, height, &params);
+    let mut film = match &args.resume {
+        None => Film::new(width, height),
+        Some(path) => resume(path, hash, width, height, &params)?,
+    };
+
+    // The callback runs on the rendering threads, which may report out of order. Only redraw the
+    // progress bar when it advances by at least 0.1%.
+    let last_report = Mutex::new(0);
+    let mut renderer =
+        Renderer::new(scene, camera).background(background).params(params).on_progress(
+            move |progress| {
+                let permille = (progress.fraction() * 1000.0) as u32;
+                let mut last_report = last_report.lock().unwrap();
+                if permille > *last_report {
+                    *last_report = permille;
+                    print_progress(progress);
+                }
+            },
+        );
+    if let Some(fog) = fog {
+        renderer = renderer.fog(fog);
+    }
+    renderer = renderer.media(media);
+
+    // Checkpoints write the current image and, if a checkpoint file is given, the accumulated
+    // samples. The last pass is skipped since both are written right after it.
+    let save_checkpoint = move |film: &Film| match &checkpoint_path {
+        None => Ok(()),
+        Some(path) => Checkpoint {
+            film: film.clone(),
+            hash,
+            seed: params.seed,
+            max_depth: params.max_depth,
+            samples_per_pixel,
+        }
+        .save(path),
+    };
+    let save_checkpoint = Arc::new(save_checkpoint);
+    if args.checkpoint_passes.is_some() || args.checkpoint_interval.is_some() {
+        let writer = writer.clone();
+        let save_checkpoint = save_checkpoint.clone();
+        let every_passes = args.checkpoint_passes;
+        let interval = args.checkpoint_interval.map(Duration::from_secs);
+        let last_checkpoint = Mutex::new((0, Instant::now()));
+        renderer = renderer.on_pass(move |film, progress| {
+            let mut last_checkpoint = last_checkpoint.lock().unwrap();
+            let (passes, time) = &mut *last_checkpoint;
+            *passes += 1;
+            let due = every_passes.is_some_and(|n| *passes >= n)
+                || interval.is_some_and(|i| time.elapsed() >= i);
+            if due && !progress.is_complete() {
+                *last_checkpoint = (0, Instant::now());
+                if let Err(e) = writer.write(&film.image()).and_then(|_| save_checkpoint(film)) {
+                    eprintln!("\nfailed to write checkpoint: {:#}", e);
+                }
+            }
+        });
+    }
+
+    // The first Ctrl-C stops the render and saves the samples taken so far, the second one exits
+    // immediately.
+    let cancellation = CancellationToken::new();
+    let token = cancellation.clone();
+    ctrlc::set_handler(move || {
+        if token.is_cancelled() {
+            std::process::exit(130);
+        }
+        token.cancel();
+    })?;
+    renderer = renderer.cancellation(cancellation.clone());
+    renderer.render_into(&mut film);
+    if cancellation.is_cancelled() {
+        println!("\nCancelled, saving the partial image");
+    } else {
+        println!("\nDone");
+    }
+
+    // Save the image and the samples to files
+    save_checkpoint(&film)?;
+    let options = SaveOptions {
+        jpeg_quality: args.jpeg_quality,
+        overwrite,
+        tone_mapping: ToneMapping::default(),
+    };
+    if let Some(path) = &args.sample_heatmap {
+        save_image(&film.sample_heatmap(), path, &options)?;
+    }
+    let aovs = (args.denoise || !args.aov.is_empty()).then(|| renderer.render_aovs(width, height));
+    for output in args.aov.iter() {
+        save_image(&aovs.as_ref().unwrap().image(output.aov), &output.path, &options)?;
+    }
+    match &aovs {
+        Some(aovs) if args.denoise => writer.write(&Denoiser::default().apply(&film, aovs)),
+        _ => writer.write(&film.image()),
+    }
+}
+
+// Loads the checkpoint at `path` and checks that it was rendered with the same scene and settings.
+fn resume(path: &Path, hash: u64, width: u32, height: u32, params: &RenderParams) -> Result<Film> {
+    let checkpoint = Checkpoint::load(path)?;
+    let (film_width, film_height) = (checkpoint.film.width(), checkpoint.film.height());
+    let mismatch = if (film_width, film_height) != (width, height) {
+        format!("the image size was {}x{}", film_width, film_height)
+    } else if checkpoint.seed != params.seed {
+        format!("the seed was {}", checkpoint.seed)
+    } else if checkpoint.max_depth != params.max_depth {
+        format!("the maximum number of bounces was {}", checkpoint.max_depth - 1)
+    } else if checkpoint.hash != hash {
+        String::from("the scene, the sampler or the filter was different")
+    } else {
+        return Ok(checkpoint.film);
+    };
+    Err(anyhow!("cannot resume from \"{}\": {}", path.display(), mismatch))
+}
+
+// Saves images to the output files. Only the first write is subject to the overwrite option, so
+// that checkpoints are replaced by later images.
+struct ImageWriter {
+    outputs: Vec<PathBuf>,
+    options: Mutex<SaveOptions>,
+}
+
+impl ImageWriter {
+    fn write(&self, img: &Rgb32FImage) -> Result<()> {
+        let mut options = self.options.lock().unwrap();
+        for path in self.outputs.iter() {
+            save_image(img, path, &options)?;
+        }
+        options.overwrite = true;
+        Ok(())
+    }
+}
+
+// Redraws a progress bar with the elapsed and estimated remaining time on the current line of the
+// terminal.
+fn print_progress(progress: &Progress) {
+    let filled =
+        ((progress.fraction() * PROGRESS_BAR_WIDTH as f32) as usize).min(PROGRESS_BAR_WIDTH);
+    let eta = progress.eta().map_or_else(|| String::from("--:--:--"), format_duration);
+    eprint!(
+        "\r[{}{}] {:3.0}% elapsed {} remaining {}",
+        "#".repeat(filled),
+        " ".repeat(PROGRESS_BAR_WIDTH - filled),
+        100.0 * progress.fraction(),
+        format_duration(progress.elapsed),
+        eta
     );
-    println!("\nDone");
+}
 
-    // Save the image to a file
-    img.save("image.ppm").context("failed to write PPM image")?;
-    Ok(())
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
 }
@@ -3,8 +3,17 @@
 // Use of this source code is governed by the MIT License described
 // in the LICENSE file.
 
-use crate::{algebra::Ray, color::RgbFloat, random::Rng, scene::HitRecord};
-use std::sync::Arc;
+// The constructors below return shared, type-erased references rather than `Self`.
+#![allow(clippy::new_ret_no_self)]
+
+use crate::{
+    algebra::{Ray, Vec3},
+    color::RgbFloat,
+    random::Rng,
+    scene::HitRecord,
+    texture::{SolidColor, TextureRef},
+};
+use std::{f32::consts::PI, sync::Arc};
 
 type Ref<T> = Arc<Box<T>>;
 
@@ -12,82 +21,202 @@ pub trait Material: Send + Sync {
     /// Scatter the given ray at the given surface. Returns a bounced ray and albedo that
     /// represents the attenuation factor for the outgoing radiance. Retuns `None` if incoming
     /// radiance is entirely absorbed.
-    fn scatter(&self, incident: &Ray, surface: &HitRecord) -> Option<(RgbFloat, Ray)>;
+    fn scatter(&self, incident: &Ray, surface: &HitRecord, rng: &Rng) -> Option<(RgbFloat, Ray)>;
+
+    /// Returns the radiance emitted by the surface towards the incident ray. Materials that are
+    /// not light sources emit no light.
+    fn emitted(&self, _surface: &HitRecord) -> RgbFloat {
+        RgbFloat::black()
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Returns the reflectance of the surface, which is recorded in the albedo AOV. Materials
+    /// without a diffuse or glossy color, such as glass and lights, are white.
+    fn albedo(&self, _surface: &HitRecord) -> RgbFloat {
+        RgbFloat::white()
+    }
+
+    /// Evaluates the scattering of light arriving from `direction` towards the incident ray.
+    /// Returns the BSDF weighted by the cosine of the angle between `direction` and the surface
+    /// normal, and the probability density with which `scatter` would have sampled `direction`.
+    ///
+    /// Returns `None` if the BSDF is singular (e.g. a perfect mirror) and cannot be evaluated for
+    /// arbitrary directions. Such materials don't receive direct light sampling.
+    fn eval(
+        &self,
+        _incident: &Ray,
+        _surface: &HitRecord,
+        _direction: &Vec3,
+    ) -> Option<(RgbFloat, f32)> {
+        None
+    }
 }
 
 pub type MaterialRef = Ref<dyn Material>;
 
 /// Lambertian diffuse material.
 pub struct Lambertian {
-    albedo: RgbFloat,
+    albedo: TextureRef,
 }
 
 impl Lambertian {
     pub fn new(albedo: RgbFloat) -> Ref<dyn Material> {
+        Lambertian::textured(SolidColor::new(albedo))
+    }
+
+    pub fn textured(albedo: TextureRef) -> Ref<dyn Material> {
         Arc::new(Box::new(Lambertian { albedo }))
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, surface: &HitRecord) -> Option<(RgbFloat, Ray)> {
-        let scattered = Rng::sample_hemisphere(&surface.normal);
-        Some((self.albedo, Ray { origin: surface.point, direction: scattered }))
+    fn scatter(&self, incident: &Ray, surface: &HitRecord, rng: &Rng) -> Option<(RgbFloat, Ray)> {
+        // Sample a cosine-weighted direction. Since the BSDF times the cosine term divided by the
+        // sample density is constant, the attenuation is simply the albedo.
+        let mut scattered = surface.normal + rng.sample_sphere();
+
+        // Catch the degenerate scatter direction.
+        if scattered.length_squared() < 1e-8 {
+            scattered = surface.normal;
+        }
+        let albedo = self.albedo.value(surface.uv, &surface.point);
+        Some((albedo, Ray { origin: surface.point, direction: scattered, time: incident.time }))
+    }
+
+    fn eval(&self, _: &Ray, surface: &HitRecord, direction: &Vec3) -> Option<(RgbFloat, f32)> {
+        let cosine = surface.normal.dot(&direction.normalized()).max(0.0);
+        let albedo = self.albedo.value(surface.uv, &surface.point);
+        Some((albedo * cosine / PI, cosine / PI))
+    }
+
+    fn albedo(&self, surface: &HitRecord) -> RgbFloat {
+        self.albedo.value(surface.uv, &surface.point)
     }
 }
 
 /// Metallic material.
 pub struct Metal {
-    albedo: RgbFloat,
+    albedo: TextureRef,
     fuzz: f32,
 }
 
 impl Metal {
     pub fn new(albedo: RgbFloat, fuzz: f32) -> Ref<dyn Material> {
+        Metal::textured(SolidColor::new(albedo), fuzz)
+    }
+
+    pub fn textured(albedo: TextureRef, fuzz: f32) -> Ref<dyn Material> {
         Arc::new(Box::new(Metal { albedo, fuzz: fuzz.clamp(0.0, 1.0) }))
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, incident: &Ray, surface: &HitRecord) -> Option<(RgbFloat, Ray)> {
+    fn scatter(&self, incident: &Ray, surface: &HitRecord, rng: &Rng) -> Option<(RgbFloat, Ray)> {
         let mirror = incident.direction.normalized().reflect(&surface.normal);
-        let scattered = mirror + self.fuzz * Rng::sample_sphere();
-        Some((self.albedo, Ray { origin: surface.point, direction: scattered }))
+        let scattered = mirror + self.fuzz * rng.sample_sphere();
+        let albedo = self.albedo.value(surface.uv, &surface.point);
+        Some((albedo, Ray { origin: surface.point, direction: scattered, time: incident.time }))
+    }
+
+    fn albedo(&self, surface: &HitRecord) -> RgbFloat {
+        self.albedo.value(surface.uv, &surface.point)
     }
 }
 
 /// Glass-like material.
 pub struct Dielectric {
     index_of_refraction: f32,
-    rng: Rng,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f32) -> Ref<dyn Material> {
-        Arc::new(Box::new(Dielectric { index_of_refraction, rng: Rng::new() }))
+        Arc::new(Box::new(Dielectric { index_of_refraction }))
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, incident: &Ray, surface: &HitRecord) -> Option<(RgbFloat, Ray)> {
+    fn scatter(&self, incident: &Ray, surface: &HitRecord, rng: &Rng) -> Option<(RgbFloat, Ray)> {
         let refraction_ratio = if surface.is_front_face {
             1.0 / self.index_of_refraction
         } else {
             self.index_of_refraction
         };
 
-        let incident = incident.direction.normalized();
-        let cos_theta = (-incident).dot(&surface.normal).min(1.0);
+        let unit_direction = incident.direction.normalized();
+        let cos_theta = (-unit_direction).dot(&surface.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract
-            || reflectance(cos_theta, refraction_ratio) > self.rng.random_float()
-        {
-            incident.reflect(&surface.normal)
-        } else {
-            incident.refract(&surface.normal, refraction_ratio)
-        };
-        Some((RgbFloat::white(), Ray { origin: surface.point, direction }))
+        let direction =
+            if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.random_float() {
+                unit_direction.reflect(&surface.normal)
+            } else {
+                unit_direction.refract(&surface.normal, refraction_ratio)
+            };
+        Some((RgbFloat::white(), Ray { origin: surface.point, direction, time: incident.time }))
+    }
+}
+
+/// Emissive material that turns any surface into an area light. The surface emits the same
+/// radiance in all directions and does not reflect any incoming light.
+pub struct DiffuseLight {
+    emit: RgbFloat,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: RgbFloat) -> Ref<dyn Material> {
+        Arc::new(Box::new(DiffuseLight { emit }))
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &Rng) -> Option<(RgbFloat, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _surface: &HitRecord) -> RgbFloat {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// The phase function of a participating medium that scatters light equally in all directions, as
+/// described in section 9 of "Ray Tracing: The Next Week". Since it doesn't depend on any surface,
+/// `eval` returns the phase function without a cosine term.
+pub struct Isotropic {
+    albedo: TextureRef,
+}
+
+impl Isotropic {
+    pub fn new(albedo: RgbFloat) -> Ref<dyn Material> {
+        Isotropic::textured(SolidColor::new(albedo))
+    }
+
+    pub fn textured(albedo: TextureRef) -> Ref<dyn Material> {
+        Arc::new(Box::new(Isotropic { albedo }))
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, incident: &Ray, surface: &HitRecord, rng: &Rng) -> Option<(RgbFloat, Ray)> {
+        let direction = rng.sample_sphere();
+        let albedo = self.albedo.value(surface.uv, &surface.point);
+        Some((albedo, Ray { origin: surface.point, direction, time: incident.time }))
+    }
+
+    fn eval(&self, _: &Ray, surface: &HitRecord, _: &Vec3) -> Option<(RgbFloat, f32)> {
+        let albedo = self.albedo.value(surface.uv, &surface.point);
+        Some((albedo / (4.0 * PI), 1.0 / (4.0 * PI)))
+    }
+
+    fn albedo(&self, surface: &HitRecord) -> RgbFloat {
+        self.albedo.value(surface.uv, &surface.point)
     }
 }
 
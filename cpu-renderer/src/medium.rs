@@ -0,0 +1,245 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::Ray,
+    color::RgbFloat,
+    material::{Isotropic, MaterialRef},
+    random::Rng,
+    scene::{HitRecord, Hittable, EPSILON},
+};
+
+/// A volume of constant density, such as smoke, that fills a convex boundary, as described in
+/// section 9 of "Ray Tracing: The Next Week". A ray that travels through the volume is scattered
+/// at a random distance, with the probability of scattering growing with the distance traveled
+/// inside. The scattered direction is chosen by `phase_function`, which is usually `Isotropic`.
+///
+/// Media aren't objects of the scene since they have no surface: the renderer samples the point at
+/// which a ray scatters in them along with the other random numbers of the sample (see
+/// `Renderer::media`).
+///
+/// A ray is considered to be inside the volume between its first two intersections with the
+/// boundary, so non-convex boundaries are not filled correctly.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    density: f32,
+    phase_function: MaterialRef,
+}
+
+impl ConstantMedium {
+    /// Creates a medium with the given probability of scattering per unit distance, measured in
+    /// the space of the boundary.
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        density: f32,
+        phase_function: MaterialRef,
+    ) -> ConstantMedium {
+        ConstantMedium { boundary, density, phase_function }
+    }
+
+    // Returns the range of the ray parameter in which the ray is inside the medium, clipped to
+    // [t_min, t_max], and the density per unit of the ray parameter.
+    fn segment(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let enter = self.boundary.bounded_hit(ray, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self.boundary.bounded_hit(ray, enter.t + 1e-4, f32::INFINITY)?;
+        let (t_enter, t_exit) = (enter.t.max(t_min), exit.t.min(t_max));
+        (t_enter < t_exit).then_some((t_enter, t_exit, self.density * ray.direction.length()))
+    }
+}
+
+/// Returns the point at which `ray` scatters in one of `media` on its way to `hit`, the closest
+/// surface along it, or `hit` itself if the ray passes through them. The media may overlap.
+pub(crate) fn scatter_in_media(
+    media: &[ConstantMedium],
+    ray: &Ray,
+    hit: Option<HitRecord>,
+    rng: &Rng,
+) -> Option<HitRecord> {
+    let t_max = hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+    let segments: Vec<(f32, f32, f32, &ConstantMedium)> = media
+        .iter()
+        .filter_map(|medium| {
+            let (t_enter, t_exit, density) = medium.segment(ray, EPSILON, t_max)?;
+            Some((t_enter, t_exit, density, medium))
+        })
+        .collect();
+    if segments.is_empty() {
+        return hit;
+    }
+
+    // Walk along the ray until the accumulated optical depth reaches one sampled from the
+    // exponential distribution. Within each interval between the ends of the segments, the
+    // densities of the media that cover it add up.
+    let [random, choice] = rng.random_2d();
+    let mut depth = -(1.0 - random).ln();
+    let mut ends: Vec<f32> = segments.iter().flat_map(|s| [s.0, s.1]).collect();
+    ends.sort_by(f32::total_cmp);
+    for interval in ends.windows(2) {
+        let (start, end) = (interval[0], interval[1]);
+        let inside = || segments.iter().filter(move |s| s.0 <= start && end <= s.1);
+        let density: f32 = inside().map(|s| s.2).sum();
+        let interval_depth = density * (end - start);
+        if density == 0.0 || interval_depth < depth {
+            depth -= interval_depth;
+            continue;
+        }
+
+        // Scatter in one of the media that cover the interval, in proportion to their density.
+        let mut choice = choice * density;
+        let medium = inside()
+            .find(|s| {
+                choice -= s.2;
+                choice < 0.0
+            })
+            .or_else(|| inside().next_back())
+            .unwrap()
+            .3;
+        return Some(scattering_record(ray, start + depth / density, &medium.phase_function));
+    }
+    hit
+}
+
+/// The fraction of light that travels along `ray` up to `t_max` without scattering in `media`.
+pub(crate) fn media_transmittance(media: &[ConstantMedium], ray: &Ray, t_max: f32) -> f32 {
+    let depth: f32 = media
+        .iter()
+        .filter_map(|medium| medium.segment(ray, EPSILON, t_max))
+        .map(|(t_enter, t_exit, density)| density * (t_exit - t_enter))
+        .sum();
+    (-depth).exp()
+}
+
+/// Homogeneous fog that fills the space between the objects of a scene. Rays that escape the scene
+/// leave the fog unattenuated, so that the background remains visible through it. Light that
+/// scatters in the fog does so equally in all directions.
+#[derive(Clone)]
+pub struct Fog {
+    density: f32,
+    phase_function: MaterialRef,
+}
+
+impl Fog {
+    /// Creates fog with the given probability of scattering per unit distance. `albedo` is the
+    /// fraction of the scattered light that isn't absorbed.
+    pub fn new(density: f32, albedo: RgbFloat) -> Fog {
+        Fog { density, phase_function: Isotropic::new(albedo) }
+    }
+
+    /// Returns the point at which `ray` scatters on its way to `hit`, the closest surface along
+    /// it, or `hit` itself if the ray reaches it.
+    pub(crate) fn scatter(
+        &self,
+        ray: &Ray,
+        hit: Option<HitRecord>,
+        rng: &Rng,
+    ) -> Option<HitRecord> {
+        let hit = hit?;
+        let t = scattering_distance(self.density, rng.random_float()) / ray.direction.length();
+        if t < hit.t {
+            return Some(scattering_record(ray, t, &self.phase_function));
+        }
+        Some(hit)
+    }
+
+    /// The fraction of light that travels the given distance without scattering.
+    pub(crate) fn transmittance(&self, distance: f32) -> f32 {
+        (-self.density * distance).exp()
+    }
+}
+
+// Samples the distance that light travels through a medium with the given density before it
+// scatters, from a uniform random number.
+fn scattering_distance(density: f32, random: f32) -> f32 {
+    -(1.0 - random).ln() / density
+}
+
+// A scattering event inside a medium. The normal faces back along the ray, which serves the
+// auxiliary outputs.
+fn scattering_record(ray: &Ray, t: f32, phase_function: &MaterialRef) -> HitRecord {
+    HitRecord {
+        point: ray.at(t),
+        normal: -ray.direction.normalized(),
+        material: phase_function.clone(),
+        t,
+        is_front_face: true,
+        uv: (0.0, 0.0),
+        object_id: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algebra::{Point3, Vec3},
+        planar::{BoxShape, Quad},
+    };
+    use std::sync::Arc;
+
+    fn cube(min: Point3, density: f32, phase_function: MaterialRef) -> ConstantMedium {
+        let max = min + Vec3::new(1.0, 1.0, 1.0);
+        let boundary = BoxShape::new(&min, &max, phase_function.clone());
+        ConstantMedium::new(Box::new(boundary), density, phase_function)
+    }
+
+    #[test]
+    fn rays_scatter_with_the_transmittance_of_the_media() {
+        // Two cubes that overlap between x = 0.5 and x = 1, so that rays along the x axis travel
+        // through an optical depth of 0.5 * 1 + 1 * 1.
+        let (thin, thick) = (Isotropic::new(RgbFloat::white()), Isotropic::new(RgbFloat::black()));
+        let media = [
+            cube(Point3::new(0.0, 0.0, 0.0), 0.5, thin.clone()),
+            cube(Point3::new(0.5, 0.0, 0.0), 1.0, thick.clone()),
+        ];
+        let rng = Rng::new(3);
+        let ray = |rng: &Rng| Ray {
+            origin: Point3::new(-1.0, rng.random_float(), rng.random_float()),
+            direction: Vec3::new(2.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let expected = (-1.5f32).exp();
+        assert!((media_transmittance(&media, &ray(&rng), f32::INFINITY) - expected).abs() < 1e-5);
+
+        let n = 20000;
+        let (mut passed, mut before_overlap, mut in_overlap, mut thick_in_overlap) = (0, 0, 0, 0);
+        for _ in 0..n {
+            let hit = match scatter_in_media(&media, &ray(&rng), None, &rng) {
+                None => {
+                    passed += 1;
+                    continue;
+                }
+                Some(hit) => hit,
+            };
+            let x = hit.point.x();
+            assert!((0.0..=1.5).contains(&x), "{}", x);
+            if x < 0.5 {
+                before_overlap += 1;
+            } else if x < 1.0 {
+                in_overlap += 1;
+                thick_in_overlap += Arc::ptr_eq(&hit.material, &thick) as u32;
+            }
+        }
+        let fraction = |count| count as f32 / n as f32;
+        assert!((fraction(passed) - expected).abs() < 0.01, "{}", fraction(passed));
+        let expected = 1.0 - (-0.25f32).exp();
+        assert!((fraction(before_overlap) - expected).abs() < 0.01);
+        assert!((fraction(in_overlap) - (-0.25f32).exp() * (1.0 - (-0.75f32).exp())).abs() < 0.01);
+        let thick_fraction = thick_in_overlap as f32 / in_overlap as f32;
+        assert!((thick_fraction - 2.0 / 3.0).abs() < 0.02, "{}", thick_fraction);
+
+        // Rays don't scatter behind the closest surface.
+        let wall = Quad::new(
+            Point3::new(0.25, -1.0, -1.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Vec3::new(0.0, 0.0, 3.0),
+            thin,
+        );
+        for _ in 0..1000 {
+            let ray = ray(&rng);
+            let hit = scatter_in_media(&media, &ray, wall.hit(&ray), &rng).unwrap();
+            assert!(hit.point.x() <= 0.25 + 1e-6, "{}", hit.point.x());
+        }
+    }
+}
@@ -0,0 +1,415 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Aabb, Point3, Ray, Vec3},
+    bvh::BvhNode,
+    material::MaterialRef,
+    random::Rng,
+    scene::{align_face_normal, HitRecord, Hittable},
+    texture::TexCoord,
+};
+use {
+    anyhow::{anyhow, Result},
+    std::sync::Arc,
+};
+
+/// Bounding boxes of triangles are padded by this amount so that axis-aligned triangles don't
+/// produce flat boxes.
+pub(crate) const BOX_PADDING: f32 = 0.0001;
+
+/// Vertex and index data for an indexed triangle mesh. Faces refer to vertices by their index
+/// into `positions` and use a counter-clockwise winding order to define the front face.
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+
+    /// Optional per-vertex shading normals. If present there must be one per position.
+    pub normals: Option<Vec<Vec3>>,
+
+    /// Optional per-vertex texture coordinates. If present there must be one per position.
+    pub uvs: Option<Vec<TexCoord>>,
+
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    fn validate(&self) -> Result<()> {
+        if self.indices.is_empty() {
+            return Err(anyhow!("mesh has no faces"));
+        }
+        let vertex_count = self.positions.len();
+        if self.normals.as_ref().is_some_and(|n| n.len() != vertex_count) {
+            return Err(anyhow!("mesh must have exactly one normal per vertex"));
+        }
+        if self.uvs.as_ref().is_some_and(|uv| uv.len() != vertex_count) {
+            return Err(anyhow!("mesh must have exactly one texture coordinate per vertex"));
+        }
+        if let Some((face, _)) =
+            self.indices.iter().enumerate().find(|(_, f)| f.iter().any(|&i| i >= vertex_count))
+        {
+            return Err(anyhow!("face {} refers to a vertex out of range", face));
+        }
+        Ok(())
+    }
+}
+
+struct Mesh {
+    data: MeshData,
+    material: MaterialRef,
+}
+
+impl Mesh {
+    fn vertices(&self, face: usize) -> [&Point3; 3] {
+        let [a, b, c] = self.data.indices[face];
+        let p = &self.data.positions;
+        [&p[a], &p[b], &p[c]]
+    }
+
+    // Returns the non-normalized outward facing geometric normal of a face. Its length equals
+    // twice the area of the face.
+    fn face_normal(&self, face: usize) -> Vec3 {
+        let [p0, p1, p2] = self.vertices(face);
+        (p1 - p0).cross(&(p2 - p0))
+    }
+
+    fn face_area(&self, face: usize) -> f32 {
+        0.5 * self.face_normal(face).length()
+    }
+
+    // Returns a uniformly distributed random point on the surface of a face.
+    fn sample_face(&self, face: usize, rng: &Rng) -> Point3 {
+        let [p0, p1, p2] = self.vertices(face);
+        let [r1, r2] = rng.random_2d();
+        let su = r1.sqrt();
+        let b1 = su * (1.0 - r2);
+        let b2 = su - b1;
+        (1.0 - su) * p0 + b1 * p1 + b2 * p2
+    }
+}
+
+// Converts an area density of `1 / area` for a point on a surface with the given normal into a
+// solid angle density as seen from `origin`.
+pub(crate) fn area_to_solid_angle_pdf(
+    origin: &Point3,
+    point: &Point3,
+    normal: &Vec3,
+    area: f32,
+) -> f32 {
+    let to_point = point - origin;
+    let distance_squared = to_point.length_squared();
+    let cosine = normal.normalized().dot(&to_point).abs() / distance_squared.sqrt();
+    if cosine == 0.0 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
+}
+
+/// A single triangle. Triangles either stand alone or refer to a face of a shared `TriangleMesh`.
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    face: usize,
+}
+
+impl Triangle {
+    /// Constructs a flat-shaded triangle with the given vertices in counter-clockwise order.
+    pub fn new(vertices: [Point3; 3], material: MaterialRef) -> Triangle {
+        let data = MeshData {
+            positions: vertices.to_vec(),
+            indices: vec![[0, 1, 2]],
+            ..Default::default()
+        };
+        Triangle { mesh: Arc::new(Mesh { data, material }), face: 0 }
+    }
+
+    fn vertices(&self) -> [&Point3; 3] {
+        self.mesh.vertices(self.face)
+    }
+}
+
+impl Hittable for Triangle {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [p0, p1, p2] = self.vertices();
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+
+        let outward_normal = self.mesh.face_normal(self.face).normalized();
+        let (is_front_face, geometric_normal) = align_face_normal(ray, &outward_normal);
+
+        // Interpolate the vertex normals if they are present. The shading normal is flipped if
+        // needed so that it lies in the same hemisphere as the geometric normal.
+        let normal = match &self.mesh.data.normals {
+            None => geometric_normal,
+            Some(normals) => {
+                let [a, b, c] = self.mesh.data.indices[self.face];
+                let n =
+                    ((1.0 - b1 - b2) * normals[a] + b1 * normals[b] + b2 * normals[c]).normalized();
+                if n.dot(&geometric_normal) < 0.0 {
+                    -n
+                } else {
+                    n
+                }
+            }
+        };
+
+        // Interpolate the texture coordinates if they are present. Otherwise, use the barycentric
+        // coordinates.
+        let uv = match &self.mesh.data.uvs {
+            None => (b1, b2),
+            Some(uvs) => {
+                let [a, b, c] = self.mesh.data.indices[self.face];
+                let b0 = 1.0 - b1 - b2;
+                (
+                    b0 * uvs[a].0 + b1 * uvs[b].0 + b2 * uvs[c].0,
+                    b0 * uvs[a].1 + b1 * uvs[b].1 + b2 * uvs[c].1,
+                )
+            }
+        };
+
+        Some(HitRecord {
+            point: ray.at(t),
+            normal,
+            material: self.mesh.material.clone(),
+            t,
+            is_front_face,
+            uv,
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices();
+        Some(Aabb::new(&p0.min(p1), &p0.max(p1)).union(&Aabb::new(p2, p2)).padded(BOX_PADDING))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.material.is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        Some(self.mesh.sample_face(self.face, rng) - origin)
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        match self.hit(&Ray { origin: *origin, direction: *direction, time: 0.0 }) {
+            None => 0.0,
+            Some(hit) => area_to_solid_angle_pdf(
+                origin,
+                &hit.point,
+                &self.mesh.face_normal(self.face),
+                self.mesh.face_area(self.face),
+            ),
+        }
+    }
+}
+
+// A face of a `TriangleMesh`. Its density is that of sampling the face as part of the mesh, which
+// picks faces in proportion to their area, so that the densities of all faces along a direction
+// add up to the density of the mesh.
+struct MeshFace {
+    triangle: Triangle,
+
+    // The fraction of the area of the mesh that the face covers.
+    weight: f32,
+}
+
+impl Hittable for MeshFace {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.triangle.bounded_hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.triangle.bounding_box()
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.weight * self.triangle.direction_pdf(origin, direction)
+    }
+}
+
+/// An indexed triangle mesh with shared vertices. Faces are organized in a BVH of their own.
+pub struct TriangleMesh {
+    mesh: Arc<Mesh>,
+    bvh: BvhNode,
+
+    // Running sum of face areas, used to pick faces proportionally to their area when sampling.
+    area_cdf: Vec<f32>,
+}
+
+impl TriangleMesh {
+    /// Returns an error if the mesh data is empty or inconsistent.
+    pub fn new(data: MeshData, material: MaterialRef) -> Result<TriangleMesh> {
+        data.validate()?;
+        let face_count = data.indices.len();
+        let mesh = Arc::new(Mesh { data, material });
+        let area_cdf: Vec<f32> = (0..face_count)
+            .scan(0.0, |sum, face| {
+                *sum += mesh.face_area(face);
+                Some(*sum)
+            })
+            .collect();
+        let total_area = *area_cdf.last().unwrap();
+        let faces: Vec<Box<dyn Hittable>> = (0..face_count)
+            .map(|face| {
+                let triangle = Triangle { mesh: mesh.clone(), face };
+                let weight = mesh.face_area(face) / total_area;
+                Box::new(MeshFace { triangle, weight }) as Box<dyn Hittable>
+            })
+            .collect();
+        Ok(TriangleMesh { mesh, bvh: BvhNode::new(faces), area_cdf })
+    }
+
+    fn total_area(&self) -> f32 {
+        *self.area_cdf.last().unwrap()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.bvh.bounded_hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.material.is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    // Samples points uniformly over the entire surface of the mesh.
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        let target = rng.random_float() * self.total_area();
+        let face = self.area_cdf.partition_point(|&a| a < target).min(self.area_cdf.len() - 1);
+        Some(self.mesh.sample_face(face, rng) - origin)
+    }
+
+    // Any of the faces that the direction passes through, not just the nearest one, could have
+    // produced it.
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.bvh.direction_pdf(origin, direction)
+    }
+}
+
+// Möller-Trumbore ray-triangle intersection. Returns the ray parameter and the barycentric
+// coordinates of the hit point with respect to `p1` and `p2`.
+fn intersect_triangle(
+    ray: &Ray,
+    p0: &Point3,
+    p1: &Point3,
+    p2: &Point3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = ray.direction.cross(&e2);
+    let det = e1.dot(&pvec);
+
+    // The ray is parallel to the plane of the triangle, or the triangle is degenerate.
+    if det.abs() < f32::EPSILON * e1.length() * e2.length() * ray.direction.length() {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin - p0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(&e1);
+    let b2 = ray.direction.dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::RgbFloat, material::Lambertian};
+
+    fn quad(normals: Option<Vec<Vec3>>) -> TriangleMesh {
+        let data = MeshData {
+            positions: vec![
+                Point3::new(-1.0, 0.0, 1.0),
+                Point3::new(1.0, 0.0, 1.0),
+                Point3::new(1.0, 0.0, -1.0),
+                Point3::new(-1.0, 0.0, -1.0),
+            ],
+            normals,
+            uvs: None,
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        };
+        TriangleMesh::new(data, Lambertian::new(RgbFloat::gray(0.5))).unwrap()
+    }
+
+    #[test]
+    fn hits_axis_aligned_mesh_from_both_sides() {
+        let mesh = quad(None);
+        let down = Ray {
+            origin: Point3::new(0.3, 2.0, 0.2),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = mesh.hit(&down).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-6);
+        assert!(hit.is_front_face);
+        assert_eq!(hit.normal.data, [0.0, 1.0, 0.0]);
+
+        let up = Ray {
+            origin: Point3::new(-0.5, -1.0, -0.7),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = mesh.hit(&up).unwrap();
+        assert!(!hit.is_front_face);
+        assert_eq!(hit.normal.data, [0.0, -1.0, 0.0]);
+
+        let miss = Ray {
+            origin: Point3::new(1.5, 1.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert!(mesh.hit(&miss).is_none());
+    }
+
+    #[test]
+    fn interpolates_vertex_normals() {
+        let tilted = Vec3::new(1.0, 1.0, 0.0).normalized();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mesh = quad(Some(vec![up, tilted, tilted, up]));
+        let ray = Ray {
+            origin: Point3::new(0.0, 1.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let normal = mesh.hit(&ray).unwrap().normal;
+        let expected = (0.5 * up + 0.5 * tilted).normalized();
+        assert!((normal - expected).length() < 1e-5);
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let data = MeshData {
+            positions: vec![Point3::new(0.0, 0.0, 0.0); 3],
+            indices: vec![[0, 1, 3]],
+            ..Default::default()
+        };
+        assert!(TriangleMesh::new(data, Lambertian::new(RgbFloat::gray(0.5))).is_err());
+    }
+}
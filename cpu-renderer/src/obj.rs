@@ -0,0 +1,392 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Point3, Vec3},
+    color::RgbFloat,
+    material::{Dielectric, Lambertian, MaterialRef, Metal},
+    mesh::{MeshData, TriangleMesh},
+    scene::Scene,
+};
+use {
+    anyhow::{anyhow, Context, Result},
+    std::{
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+        str::{FromStr, SplitWhitespace},
+    },
+};
+
+/// Loads all faces in the Wavefront OBJ file at `path` as triangle meshes. A separate mesh is
+/// created for each combination of group and material. Material libraries referenced by the file
+/// are resolved relative to the directory of the OBJ file.
+///
+/// If `material` is provided, it is applied to all faces instead of the materials assigned by the
+/// file.
+pub fn load_obj(path: &Path, material: Option<MaterialRef>) -> Result<Scene> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("failed to read OBJ file \"{}\"", path.display()))?;
+    let dir = path.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+    parse_obj(&source, &dir, material).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+// Vertex attribute indices of a single face corner, resolved to zero-based indices.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct MeshBuilder {
+    data: MeshData,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+
+    // Attributes are only kept if every corner of every face provides them.
+    has_normals: bool,
+    has_uvs: bool,
+
+    // Maps OBJ attribute index tuples to the unified vertex index within this mesh.
+    vertices: HashMap<Corner, usize>,
+}
+
+impl MeshBuilder {
+    fn new() -> MeshBuilder {
+        MeshBuilder { has_normals: true, has_uvs: true, ..Default::default() }
+    }
+
+    fn vertex(&mut self, corner: Corner, obj: &ObjParser) -> usize {
+        if let Some(&index) = self.vertices.get(&corner) {
+            return index;
+        }
+        let (p, uv, n) = corner;
+        let index = self.data.positions.len();
+        self.data.positions.push(obj.positions[p]);
+        self.uvs.push(uv.map_or((0.0, 0.0), |i| obj.uvs[i]));
+        self.normals.push(n.map_or(Vec3::new(0.0, 0.0, 0.0), |i| obj.normals[i]));
+        self.has_uvs &= uv.is_some();
+        self.has_normals &= n.is_some();
+        self.vertices.insert(corner, index);
+        index
+    }
+
+    fn build(self, material: MaterialRef) -> Result<TriangleMesh> {
+        let mut data = self.data;
+        data.normals = if self.has_normals { Some(self.normals) } else { None };
+        data.uvs = if self.has_uvs { Some(self.uvs) } else { None };
+        TriangleMesh::new(data, material)
+    }
+}
+
+#[derive(Default)]
+struct ObjParser {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+
+    materials: HashMap<String, MaterialRef>,
+    group: String,
+    material: Option<String>,
+
+    // Meshes in the order they were first referenced, keyed by group and material name.
+    meshes: Vec<((String, Option<String>), MeshBuilder)>,
+}
+
+impl ObjParser {
+    fn parse_line(&mut self, line: &str, dir: &Path) -> Result<()> {
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => return Ok(()),
+        };
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats::<3>(&mut tokens, "vertex position")?;
+                self.positions.push(Point3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats::<3>(&mut tokens, "vertex normal")?;
+                self.normals.push(Vec3::new(x, y, z).normalized());
+            }
+            "vt" => {
+                let [u, v] = parse_floats::<2>(&mut tokens, "texture coordinate")?;
+                self.uvs.push((u, v));
+            }
+            "f" => self.parse_face(tokens)?,
+            "g" | "o" => self.group = tokens.collect::<Vec<_>>().join(" "),
+            "usemtl" => {
+                let name = rest_of_line(tokens, "usemtl")?;
+                if !self.materials.contains_key(&name) {
+                    return Err(anyhow!("undefined material \"{}\"", name));
+                }
+                self.material = Some(name);
+            }
+            "mtllib" => {
+                for file in tokens {
+                    let path = dir.join(file);
+                    self.materials.extend(load_mtl(&path)?);
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are not supported.
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_face(&mut self, tokens: SplitWhitespace) -> Result<()> {
+        let corners = tokens.map(|t| self.parse_corner(t)).collect::<Result<Vec<_>>>()?;
+        if corners.len() < 3 {
+            return Err(anyhow!("a face needs at least 3 vertices, found {}", corners.len()));
+        }
+
+        let key = (self.group.clone(), self.material.clone());
+        let mesh_index = match self.meshes.iter().position(|(k, _)| *k == key) {
+            Some(i) => i,
+            None => {
+                self.meshes.push((key, MeshBuilder::new()));
+                self.meshes.len() - 1
+            }
+        };
+
+        // Temporarily take the builder out so that it can read the attribute arrays.
+        let mut mesh = std::mem::take(&mut self.meshes[mesh_index].1);
+        let indices: Vec<usize> = corners.into_iter().map(|c| mesh.vertex(c, self)).collect();
+
+        // Triangulate quads and polygons as a fan around the first vertex.
+        for i in 1..indices.len() - 1 {
+            mesh.data.indices.push([indices[0], indices[i], indices[i + 1]]);
+        }
+        self.meshes[mesh_index].1 = mesh;
+        Ok(())
+    }
+
+    // Parses a face corner in one of the forms "v", "v/vt", "v//vn" or "v/vt/vn".
+    fn parse_corner(&self, token: &str) -> Result<Corner> {
+        let mut parts = token.split('/');
+        let position = resolve_index(parts.next(), self.positions.len(), "vertex")?
+            .ok_or_else(|| anyhow!("missing vertex index in \"{}\"", token))?;
+        let uv = resolve_index(parts.next(), self.uvs.len(), "texture coordinate")?;
+        let normal = resolve_index(parts.next(), self.normals.len(), "normal")?;
+        if parts.next().is_some() {
+            return Err(anyhow!("malformed face vertex \"{}\"", token));
+        }
+        Ok((position, uv, normal))
+    }
+
+    fn finish(self, material_override: Option<MaterialRef>) -> Result<Scene> {
+        let default_material = Lambertian::new(RgbFloat::gray(0.8));
+        let ObjParser { meshes, materials, .. } = self;
+        let mut scene: Scene = Vec::new();
+        for ((_, material), mesh) in meshes {
+            let material = match (&material_override, material) {
+                (Some(m), _) => m.clone(),
+                (None, Some(m)) => materials[&m].clone(),
+                (None, None) => default_material.clone(),
+            };
+            scene.push(Box::new(mesh.build(material)?));
+        }
+        if scene.is_empty() {
+            return Err(anyhow!("file contains no faces"));
+        }
+        Ok(scene)
+    }
+}
+
+fn parse_obj(source: &str, dir: &Path, material: Option<MaterialRef>) -> Result<Scene> {
+    let mut parser = ObjParser::default();
+    for (number, line) in source.lines().enumerate() {
+        parser
+            .parse_line(strip_comment(line), dir)
+            .map_err(|e| anyhow!("line {}: {}", number + 1, e))?;
+    }
+    parser.finish(material)
+}
+
+/// Material properties as specified in an MTL file.
+struct MtlEntry {
+    diffuse: RgbFloat,
+    specular: RgbFloat,
+    shininess: f32,
+    index_of_refraction: Option<f32>,
+    opacity: f32,
+    illumination_model: u32,
+}
+
+impl Default for MtlEntry {
+    fn default() -> MtlEntry {
+        MtlEntry {
+            diffuse: RgbFloat::gray(0.8),
+            specular: RgbFloat::black(),
+            shininess: 0.0,
+            index_of_refraction: None,
+            opacity: 1.0,
+            illumination_model: 2,
+        }
+    }
+}
+
+impl MtlEntry {
+    /// Maps the MTL properties onto the closest available material:
+    ///   - Transparent materials (`d` < 1, `Tr` > 0 or illumination models 4, 6, 7 and 9) become
+    ///     `Dielectric` with the index of refraction given by `Ni`.
+    ///   - Reflective materials (illumination model 3) become `Metal` with the specular color as
+    ///     albedo and a fuzz factor derived from the specular exponent `Ns`.
+    ///   - Everything else becomes `Lambertian` with the diffuse color as albedo.
+    fn to_material(&self) -> MaterialRef {
+        if self.opacity < 1.0 || matches!(self.illumination_model, 4 | 6 | 7 | 9) {
+            Dielectric::new(self.index_of_refraction.unwrap_or(1.5))
+        } else if self.illumination_model == 3 {
+            Metal::new(self.specular, (2.0 / (self.shininess + 2.0)).sqrt())
+        } else {
+            Lambertian::new(self.diffuse)
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, MaterialRef>> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("failed to read MTL file \"{}\"", path.display()))?;
+    parse_mtl(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+fn parse_mtl(source: &str) -> Result<HashMap<String, MaterialRef>> {
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        parse_mtl_line(strip_comment(line), &mut entries)
+            .map_err(|e| anyhow!("line {}: {}", number + 1, e))?;
+    }
+    Ok(entries.into_iter().map(|(name, entry)| (name, entry.to_material())).collect())
+}
+
+fn parse_mtl_line(line: &str, entries: &mut Vec<(String, MtlEntry)>) -> Result<()> {
+    let mut tokens = line.split_whitespace();
+    let keyword = match tokens.next() {
+        Some(k) => k,
+        None => return Ok(()),
+    };
+    if keyword == "newmtl" {
+        entries.push((rest_of_line(tokens, "newmtl")?, MtlEntry::default()));
+        return Ok(());
+    }
+
+    let entry = match entries.last_mut() {
+        Some((_, entry)) => entry,
+        None => return Err(anyhow!("\"{}\" precedes the first \"newmtl\" statement", keyword)),
+    };
+    match keyword {
+        "Kd" => {
+            let [r, g, b] = parse_floats::<3>(&mut tokens, "diffuse color")?;
+            entry.diffuse = RgbFloat::new(r, g, b);
+        }
+        "Ks" => {
+            let [r, g, b] = parse_floats::<3>(&mut tokens, "specular color")?;
+            entry.specular = RgbFloat::new(r, g, b);
+        }
+        "Ns" => entry.shininess = parse_floats::<1>(&mut tokens, "specular exponent")?[0],
+        "Ni" => {
+            entry.index_of_refraction =
+                Some(parse_floats::<1>(&mut tokens, "index of refraction")?[0])
+        }
+        "d" => entry.opacity = parse_floats::<1>(&mut tokens, "dissolve")?[0],
+        "Tr" => entry.opacity = 1.0 - parse_floats::<1>(&mut tokens, "transparency")?[0],
+        "illum" => {
+            let token = tokens.next().ok_or_else(|| anyhow!("missing illumination model"))?;
+            entry.illumination_model =
+                token.parse().map_err(|_| anyhow!("invalid illumination model \"{}\"", token))?;
+        }
+        // Ambient and emissive colors, texture maps and other properties are ignored.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split('#').next().unwrap_or("")
+}
+
+fn rest_of_line(tokens: SplitWhitespace, keyword: &str) -> Result<String> {
+    let name = tokens.collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err(anyhow!("\"{}\" requires a name", keyword));
+    }
+    Ok(name)
+}
+
+// Parses exactly `N` floating point values from `tokens`. Any extra values (such as the optional
+// "w" component of vertex positions) are ignored.
+fn parse_floats<const N: usize>(tokens: &mut SplitWhitespace, what: &str) -> Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        let token = tokens.next().ok_or_else(|| anyhow!("{} needs {} components", what, N))?;
+        *value = f32::from_str(token)
+            .map_err(|_| anyhow!("invalid number \"{}\" in {}", token, what))?;
+    }
+    Ok(values)
+}
+
+// Converts a one-based (or negative, relative to the end) OBJ index to a zero-based index into an
+// attribute list of length `count`. Returns `None` if the index is absent or empty.
+fn resolve_index(token: Option<&str>, count: usize, what: &str) -> Result<Option<usize>> {
+    let token = match token {
+        None | Some("") => return Ok(None),
+        Some(t) => t,
+    };
+    let index: i64 = token.parse().map_err(|_| anyhow!("invalid {} index \"{}\"", what, token))?;
+    let resolved = match index {
+        i if i > 0 => i - 1,
+        i if i < 0 => count as i64 + i,
+        _ => return Err(anyhow!("{} index must not be 0", what)),
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(anyhow!("{} index {} is out of range", what, index));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebra::Ray, scene::Hittable};
+
+    const QUAD: &str = "
+        # A unit quad on the XZ plane
+        v -1 0 1
+        v 1 0 1
+        v 1 0 -1
+        v -1 0 -1
+        vn 0 1 0
+        g quad
+        f -4//1 -3//1 -2//1 -1//1
+    ";
+
+    #[test]
+    fn triangulates_quads_with_negative_indices() {
+        let scene = parse_obj(QUAD, Path::new(""), None).unwrap();
+        assert_eq!(scene.len(), 1);
+        for (x, z) in [(0.5, 0.5), (-0.5, -0.5)] {
+            let ray = Ray {
+                origin: Point3::new(x, 1.0, z),
+                direction: Vec3::new(0.0, -1.0, 0.0),
+                time: 0.0,
+            };
+            let hit = scene.hit(&ray).unwrap();
+            assert!((hit.t - 1.0).abs() < 1e-6);
+            assert_eq!(hit.normal.data, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn splits_meshes_by_group() {
+        let source = format!("{}\ng other\nf 1 2 3\n", QUAD);
+        assert_eq!(parse_obj(&source, Path::new(""), None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let error = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n", Path::new(""), None).err().unwrap();
+        assert_eq!(error.to_string(), "line 3: vertex index 3 is out of range");
+
+        let error = parse_mtl("newmtl a\nKd 1 x 1\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 2: invalid number \"x\" in diffuse color");
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    color::{srgb_encode, RgbFloat},
+    tonemap::ToneMapping,
+};
+use {
+    anyhow::{anyhow, Context, Result},
+    image::{
+        codecs::{hdr::HdrEncoder, pnm::PnmSubtype, pnm::SampleEncoding},
+        ImageOutputFormat, Rgb, Rgb32FImage, RgbImage,
+    },
+    std::{
+        fs::{File, OpenOptions},
+        io::{BufWriter, ErrorKind},
+        path::Path,
+    },
+};
+
+/// The file formats that rendered images can be saved in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+    Jpeg,
+    Tga,
+    Bmp,
+
+    /// OpenEXR, which stores linear radiance in floating point.
+    OpenExr,
+
+    /// Radiance RGBE, which stores linear radiance with a shared 8-bit exponent.
+    Hdr,
+}
+
+impl OutputFormat {
+    /// Picks the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Result<OutputFormat> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        Ok(match extension.to_ascii_lowercase().as_str() {
+            "ppm" => OutputFormat::Ppm,
+            "png" => OutputFormat::Png,
+            "jpg" | "jpeg" => OutputFormat::Jpeg,
+            "tga" => OutputFormat::Tga,
+            "bmp" => OutputFormat::Bmp,
+            "exr" => OutputFormat::OpenExr,
+            "hdr" => OutputFormat::Hdr,
+            _ => {
+                return Err(anyhow!(
+                    "unsupported image format for \"{}\" (expected .ppm, .png, .jpg, .tga, .bmp, \
+                     .exr or .hdr)",
+                    path.display()
+                ))
+            }
+        })
+    }
+
+    /// Returns true if the format preserves radiance above 1.0.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, OutputFormat::OpenExr | OutputFormat::Hdr)
+    }
+}
+
+/// Options that control how images are written.
+pub struct SaveOptions {
+    /// JPEG quality between 1 and 100.
+    pub jpeg_quality: u8,
+
+    /// Replace an existing file at the output path instead of failing.
+    pub overwrite: bool,
+
+    /// Converts radiance to display colors for formats that store 8 bits per channel.
+    pub tone_mapping: ToneMapping,
+}
+
+/// Saves a framebuffer of linear radiance to `path`, picking the file format from its extension.
+/// HDR formats store the radiance as is. All other formats are tone mapped and encoded as sRGB with
+/// 8 bits per channel.
+pub fn save_image(img: &Rgb32FImage, path: &Path, options: &SaveOptions) -> Result<()> {
+    let format = OutputFormat::from_path(path)?;
+    let file = if options.overwrite {
+        File::create(path)
+    } else {
+        OpenOptions::new().write(true).create_new(true).open(path)
+    };
+    let file = file.map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => {
+            anyhow!("\"{}\" already exists, use --force to overwrite it", path.display())
+        }
+        _ => anyhow!("failed to create \"{}\": {}", path.display(), e),
+    })?;
+
+    let mut writer = BufWriter::new(file);
+    let result = match format {
+        OutputFormat::OpenExr => img.write_to(&mut writer, ImageOutputFormat::OpenExr),
+        OutputFormat::Hdr => {
+            let pixels: Vec<Rgb<f32>> = img.pixels().copied().collect();
+            HdrEncoder::new(&mut writer).encode(
+                &pixels,
+                img.width() as usize,
+                img.height() as usize,
+            )
+        }
+        _ => {
+            let format = match format {
+                OutputFormat::Ppm => {
+                    ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                }
+                OutputFormat::Png => ImageOutputFormat::Png,
+                OutputFormat::Jpeg => ImageOutputFormat::Jpeg(options.jpeg_quality),
+                OutputFormat::Tga => ImageOutputFormat::Tga,
+                _ => ImageOutputFormat::Bmp,
+            };
+            to_ldr(img, &options.tone_mapping).write_to(&mut writer, format)
+        }
+    };
+    result.with_context(|| format!("failed to write image \"{}\"", path.display()))
+}
+
+fn to_ldr(img: &Rgb32FImage, tone_mapping: &ToneMapping) -> RgbImage {
+    let img = tone_mapping.apply(img);
+    RgbImage::from_fn(img.width(), img.height(), |col, row| {
+        let color = RgbFloat::from(img.get_pixel(col, row));
+        RgbFloat::new(srgb_encode(color.r()), srgb_encode(color.g()), srgb_encode(color.b())).into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tonemap::ToneMapper;
+
+    const OPTIONS: SaveOptions = SaveOptions {
+        jpeg_quality: 90,
+        overwrite: true,
+        tone_mapping: ToneMapping {
+            tone_mapper: ToneMapper::Clamp,
+            exposure: 0.0,
+            white_point: None,
+        },
+    };
+
+    #[test]
+    fn hdr_formats_preserve_radiance() {
+        let mut img = Rgb32FImage::new(4, 2);
+        img.put_pixel(1, 1, Rgb([8.0, 0.5, 0.25]));
+        let dir = std::env::temp_dir();
+        for name in ["output_test.exr", "output_test.hdr"] {
+            let path = dir.join(name);
+            save_image(&img, &path, &OPTIONS).unwrap();
+
+            // The `image` crate converts Radiance HDR files to 8 bits per channel when opened
+            // generically, so decode them directly.
+            let loaded = if name.ends_with(".hdr") {
+                let reader = std::io::BufReader::new(File::open(&path).unwrap());
+                let decoder = image::codecs::hdr::HdrDecoder::new(reader).unwrap();
+                let pixels = decoder.read_image_hdr().unwrap();
+                Rgb32FImage::from_vec(4, 2, pixels.iter().flat_map(|p| p.0).collect()).unwrap()
+            } else {
+                image::open(&path).unwrap().into_rgb32f()
+            };
+            std::fs::remove_file(&path).unwrap();
+            let pixel = loaded.get_pixel(1, 1);
+            for (a, b) in pixel.0.iter().zip([8.0, 0.5, 0.25]) {
+                assert!((a - b).abs() < 0.01 * b, "{}: {:?}", name, pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn ldr_formats_round_trip() {
+        let img = Rgb32FImage::from_pixel(2, 2, Rgb([0.25, 1.0, 4.0]));
+        let dir = std::env::temp_dir();
+        for name in ["output_test.ppm", "output_test.png", "output_test.tga", "output_test.bmp"] {
+            let path = dir.join(name);
+            save_image(&img, &path, &OPTIONS).unwrap();
+            let loaded = image::open(&path).unwrap().into_rgb8();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.get_pixel(1, 1).0, [137, 255, 255], "{}", name);
+        }
+    }
+
+    #[test]
+    fn refuses_to_overwrite() {
+        let img = Rgb32FImage::new(1, 1);
+        let path = std::env::temp_dir().join("output_test_overwrite.png");
+        save_image(&img, &path, &OPTIONS).unwrap();
+        let options = SaveOptions { overwrite: false, ..OPTIONS };
+        let error = save_image(&img, &path, &options).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("already exists"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unknown_extensions() {
+        assert_eq!(OutputFormat::from_path(Path::new("a/b.JPEG")).unwrap(), OutputFormat::Jpeg);
+        assert!(OutputFormat::from_path(Path::new("image.gif")).is_err());
+        assert!(OutputFormat::from_path(Path::new("image")).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Aabb, Point3, Ray, Vec3},
+    material::MaterialRef,
+    mesh::{area_to_solid_angle_pdf, BOX_PADDING},
+    random::Rng,
+    scene::{align_face_normal, orthonormal_basis, HitRecord, Hittable},
+};
+use std::f32::consts::PI;
+
+/// A parallelogram spanned by the edges `u` and `v` from the corner `q`, as described in section
+/// 6 of "Ray Tracing: The Next Week". The front face is on the side that `u` x `v` points to.
+///
+/// The texture coordinates are the coordinates of the hit point along `u` and `v`, from 0 to 1.
+#[derive(Clone)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    material: MaterialRef,
+
+    // The unit normal, and the vector that maps points on the plane to their coordinates along `u`
+    // and `v`.
+    normal: Vec3,
+    w: Vec3,
+    area: f32,
+}
+
+impl Quad {
+    /// The edges must not be parallel.
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: MaterialRef) -> Quad {
+        let n = u.cross(&v);
+        let w = n / n.length_squared();
+        Quad { q, u, v, material, normal: n.normalized(), w, area: n.length() }
+    }
+}
+
+impl Hittable for Quad {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let t = intersect_plane(ray, &self.q, &self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.q;
+        let alpha = self.w.dot(&offset.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&offset));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let (is_front_face, normal) = align_face_normal(ray, &self.normal);
+        Some(HitRecord {
+            point,
+            normal,
+            material: self.material.clone(),
+            t,
+            is_front_face,
+            uv: (alpha, beta),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (q, u, v) = (self.q, self.u, self.v);
+        let bounds = Aabb::new(&q, &(q + u + v)).union(&Aabb::new(&(q + u), &(q + v)));
+        Some(bounds.padded(BOX_PADDING))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        let [r1, r2] = rng.random_2d();
+        Some(self.q + r1 * self.u + r2 * self.v - origin)
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        match self.hit(&Ray { origin: *origin, direction: *direction, time: 0.0 }) {
+            None => 0.0,
+            Some(hit) => area_to_solid_angle_pdf(origin, &hit.point, &self.normal, self.area),
+        }
+    }
+}
+
+/// A disk with the given center and radius. The front face is on the side that `normal` points to.
+///
+/// The texture coordinates project the unit square onto the disk, so that images are mapped onto
+/// it like a decal.
+pub struct Disk {
+    center: Point3,
+    radius: f32,
+    material: MaterialRef,
+
+    // An orthonormal basis in which `normal` is the third vector.
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl Disk {
+    /// `normal` doesn't need to be of unit length, but must be non-zero.
+    pub fn new(center: Point3, normal: Vec3, radius: f32, material: MaterialRef) -> Disk {
+        let normal = normal.normalized();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Disk { center, radius: radius.abs(), material, tangent, bitangent, normal }
+    }
+}
+
+impl Hittable for Disk {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let t = intersect_plane(ray, &self.center, &self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = (point - self.center) / self.radius;
+        if offset.length_squared() > 1.0 {
+            return None;
+        }
+        let (is_front_face, normal) = align_face_normal(ray, &self.normal);
+        Some(HitRecord {
+            point,
+            normal,
+            material: self.material.clone(),
+            t,
+            is_front_face,
+            uv: (0.5 + 0.5 * offset.dot(&self.tangent), 0.5 + 0.5 * offset.dot(&self.bitangent)),
+            object_id: 0,
+        })
+    }
+
+    // The extent of the disk along each axis is the radius scaled by the sine of the angle
+    // between the axis and the normal.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = &self.normal.data;
+        let extent = [0, 1, 2].map(|i| self.radius * (1.0 - n[i] * n[i]).max(0.0).sqrt());
+        let extent = Vec3::new(extent[0], extent[1], extent[2]);
+        Some(Aabb::new(&(self.center - extent), &(self.center + extent)).padded(BOX_PADDING))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        let p = self.radius * rng.sample_disk();
+        Some(self.center + p.x() * self.tangent + p.y() * self.bitangent - origin)
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        match self.hit(&Ray { origin: *origin, direction: *direction, time: 0.0 }) {
+            None => 0.0,
+            Some(hit) => {
+                let area = PI * self.radius * self.radius;
+                area_to_solid_angle_pdf(origin, &hit.point, &self.normal, area)
+            }
+        }
+    }
+}
+
+/// An infinite plane through `point`. The front face is on the side that `normal` points to.
+///
+/// The texture coordinates are the coordinates of the hit point along two perpendicular directions
+/// in the plane, wrapped to repeat every unit of distance. Planes are unbounded and therefore
+/// can't be sampled as light sources.
+pub struct Plane {
+    point: Point3,
+    material: MaterialRef,
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl Plane {
+    /// `normal` doesn't need to be of unit length, but must be non-zero.
+    pub fn new(point: Point3, normal: Vec3, material: MaterialRef) -> Plane {
+        let normal = normal.normalized();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Plane { point, material, tangent, bitangent, normal }
+    }
+}
+
+impl Hittable for Plane {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let t = intersect_plane(ray, &self.point, &self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.point;
+        let (is_front_face, normal) = align_face_normal(ray, &self.normal);
+        Some(HitRecord {
+            point,
+            normal,
+            material: self.material.clone(),
+            t,
+            is_front_face,
+            uv: (
+                offset.dot(&self.tangent).rem_euclid(1.0),
+                offset.dot(&self.bitangent).rem_euclid(1.0),
+            ),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+/// An axis-aligned box made of six quads with outward facing normals. Each face has its own
+/// texture coordinates from 0 to 1.
+pub struct BoxShape {
+    faces: [Quad; 6],
+    bounds: Aabb,
+    area: f32,
+}
+
+impl BoxShape {
+    /// Creates the box with the given opposite corners, which must differ along every axis.
+    pub fn new(a: &Point3, b: &Point3, material: MaterialRef) -> BoxShape {
+        let bounds = Aabb::new(a, b);
+        let (min, max) = (bounds.min, bounds.max);
+        let size = max - min;
+        let dx = Vec3::new(size.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, size.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, size.z());
+        let quad = |q: Point3, u: Vec3, v: Vec3| Quad::new(q, u, v, material.clone());
+        let faces = [
+            quad(Point3::new(min.x(), min.y(), max.z()), dx, dy),
+            quad(Point3::new(max.x(), min.y(), max.z()), -dz, dy),
+            quad(Point3::new(max.x(), min.y(), min.z()), -dx, dy),
+            quad(min, dz, dy),
+            quad(Point3::new(min.x(), max.y(), max.z()), dx, -dz),
+            quad(min, dx, dz),
+        ];
+        let area = faces.iter().map(|face| face.area).sum();
+        BoxShape { faces, bounds, area }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        if !self.bounds.hit(ray, t_min, t_max) {
+            return None;
+        }
+        let mut nearest_hit: Option<HitRecord> = None;
+        for face in self.faces.iter() {
+            if let Some(hit) =
+                face.bounded_hit(ray, t_min, nearest_hit.as_ref().map_or(t_max, |h| h.t))
+            {
+                nearest_hit = Some(hit);
+            }
+        }
+        nearest_hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.faces[0].is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    // Picks a face proportionally to its area, which samples points uniformly over the surface.
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        let mut target = rng.random_float() * self.area;
+        for face in self.faces.iter() {
+            if target < face.area {
+                return face.sample_direction(origin, rng);
+            }
+            target -= face.area;
+        }
+        self.faces[5].sample_direction(origin, rng)
+    }
+
+    // A direction may pass through two faces, either of which could have been sampled.
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.faces
+            .iter()
+            .map(|face| face.area / self.area * face.direction_pdf(origin, direction))
+            .sum()
+    }
+}
+
+// Intersects a ray with the plane through `point` with the given normal. Returns the ray parameter
+// of the hit, or `None` if it's outside of the range or the ray is parallel to the plane.
+fn intersect_plane(
+    ray: &Ray,
+    point: &Point3,
+    normal: &Vec3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<f32> {
+    let t = normal.dot(&(point - ray.origin)) / normal.dot(&ray.direction);
+
+    // Rays that are parallel to the plane produce an infinite or NaN parameter.
+    (t >= t_min && t <= t_max && t.is_finite()).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::RgbFloat, material::Lambertian};
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: Point3::new(origin[0], origin[1], origin[2]),
+            direction: Vec3::new(direction[0], direction[1], direction[2]),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn quads_and_disks_report_uvs_and_normals() {
+        let material = Lambertian::new(RgbFloat::gray(0.5));
+        let quad = Quad::new(
+            Point3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            material.clone(),
+        );
+        let hit = quad.hit(&ray([1.5, 5.0, -3.0], [0.0, -1.0, 0.0])).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5 && hit.is_front_face);
+        assert!((hit.uv.0 - 0.25).abs() < 1e-5 && (hit.uv.1 - 0.75).abs() < 1e-5);
+        assert_eq!(hit.normal.data, [0.0, 1.0, 0.0]);
+        let hit = quad.hit(&ray([1.5, -5.0, -3.0], [0.0, 1.0, 0.0])).unwrap();
+        assert!(!hit.is_front_face && hit.normal.y() == -1.0);
+        assert!(quad.hit(&ray([0.5, 5.0, -3.0], [0.0, -1.0, 0.0])).is_none());
+        assert!(quad.hit(&ray([1.5, 0.0, 5.0], [0.0, 0.0, -1.0])).is_none());
+
+        let disk = Disk::new(Point3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 3.0), 2.0, material);
+        let hit = disk.hit(&ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0])).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5 && hit.is_front_face);
+        assert!((hit.uv.0 - 0.5).abs() < 1e-5 && (hit.uv.1 - 0.5).abs() < 1e-5);
+        assert!(disk.hit(&ray([1.9, 0.0, 0.0], [0.0, 0.0, -1.0])).is_some());
+        assert!(disk.hit(&ray([1.5, 1.5, 0.0], [0.0, 0.0, -1.0])).is_none());
+        let bounds = disk.bounding_box().unwrap();
+        assert!((bounds.max.x() - 2.0).abs() < 1e-3 && (bounds.max.z() + 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn planes_are_unbounded() {
+        let plane = Plane::new(
+            Point3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Lambertian::new(RgbFloat::gray(0.5)),
+        );
+        let hit = plane.hit(&ray([1000.0, 0.0, -1000.0], [1.0, -1.0, 0.0])).unwrap();
+        assert!((hit.point.y() + 1.0).abs() < 1e-3 && hit.normal.y() == 1.0);
+        assert!((0.0..1.0).contains(&hit.uv.0) && (0.0..1.0).contains(&hit.uv.1));
+        assert!(plane.hit(&ray([0.0, 0.0, 0.0], [1.0, 0.0, 0.0])).is_none());
+        assert!(plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn boxes_face_outwards() {
+        let shape = BoxShape::new(
+            &Point3::new(1.0, 2.0, 3.0),
+            &Point3::new(-1.0, 0.0, 0.0),
+            Lambertian::new(RgbFloat::gray(0.5)),
+        );
+        let center = [0.0, 1.0, 1.5];
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut direction = [0.0; 3];
+                direction[axis] = sign;
+                let origin = [0, 1, 2].map(|i| center[i] - 10.0 * direction[i]);
+                let hit = shape.hit(&ray(origin, direction)).unwrap();
+                assert!(hit.is_front_face && hit.normal.data[axis] == -sign);
+
+                // From the inside, the far face is hit from the back.
+                let hit = shape.hit(&ray(center, direction)).unwrap();
+                assert!(!hit.is_front_face && hit.normal.data[axis] == -sign);
+            }
+        }
+        assert_eq!(shape.area, 2.0 * (2.0 * 2.0 + 2.0 * 3.0 + 2.0 * 3.0));
+    }
+}
@@ -0,0 +1,585 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Aabb, Point3, Ray, Vec3},
+    material::MaterialRef,
+    scene::{align_face_normal, HitRecord, Hittable},
+    texture::TexCoord,
+};
+use std::{f32::consts::PI, iter::FromIterator};
+
+// The surfaces below are defined around the y axis and can be placed anywhere with an `Instance`.
+// Their U texture coordinate follows the convention of spheres and increases with the angle around
+// the y axis, starting from -X. Caps are textured by projecting the unit square onto them along the
+// y axis. These surfaces can't be sampled, so emissive ones are left out of the light list and
+// only light the rays that hit them at random.
+
+/// A cylinder with the given radius between `y_min` and `y_max`. V goes from 0 at `y_min` to 1 at
+/// `y_max`.
+#[derive(Clone)]
+pub struct Cylinder {
+    pub radius: f32,
+    pub y_min: f32,
+    pub y_max: f32,
+
+    /// Closes both ends with disks.
+    pub capped: bool,
+
+    pub material: MaterialRef,
+}
+
+/// A cone with a base of the given radius at y = 0 and its apex at y = `height`. V goes from 0 at
+/// the base to 1 at the apex.
+#[derive(Clone)]
+pub struct Cone {
+    pub radius: f32,
+    pub height: f32,
+
+    /// Closes the base with a disk.
+    pub capped: bool,
+
+    pub material: MaterialRef,
+}
+
+/// The paraboloid x² + z² = radius² y / y_max, which opens upwards from the origin and has the
+/// given radius at `y_max`, cut off between `y_min` and `y_max`. V goes from 0 at `y_min` to 1 at
+/// `y_max`.
+#[derive(Clone)]
+pub struct Paraboloid {
+    pub radius: f32,
+
+    /// Must not be negative.
+    pub y_min: f32,
+    pub y_max: f32,
+
+    /// Closes the top, and the bottom if `y_min` is positive, with disks.
+    pub capped: bool,
+
+    pub material: MaterialRef,
+}
+
+/// A torus whose tube of radius `minor_radius` circles the y axis at a distance of `major_radius`
+/// from the origin. V goes around the tube, starting at its outer equator and moving towards +Y.
+#[derive(Clone)]
+pub struct Torus {
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: MaterialRef,
+}
+
+impl Hittable for Cylinder {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let ([ox, _, oz], [dx, _, dz]) = (to_f64(&ray.origin), to_f64(&ray.direction));
+        let r = self.radius as f64;
+        let roots = solve_quadratic(
+            dx * dx + dz * dz,
+            2.0 * (ox * dx + oz * dz),
+            ox * ox + oz * oz - r * r,
+        );
+        let mut nearest =
+            first_root(ray, &roots, t_min, t_max, |p| (self.y_min..=self.y_max).contains(&p.y()))
+                .map(|(t, p)| {
+                    let normal = Vec3::new(p.x(), 0.0, p.z()) / self.radius;
+                    (t, normal, (azimuth(&p), (p.y() - self.y_min) / (self.y_max - self.y_min)))
+                });
+        if self.capped {
+            for (y, side) in [(self.y_min, -1.0), (self.y_max, 1.0)] {
+                hit_cap(ray, y, self.radius, side, t_min, t_max, &mut nearest);
+            }
+        }
+        let (t, normal, uv) = nearest?;
+        Some(hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(revolved_bounds(self.radius, self.y_min, self.y_max))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl Hittable for Cone {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let ([ox, oy, oz], [dx, dy, dz]) = (to_f64(&ray.origin), to_f64(&ray.direction));
+
+        // The cone is x² + z² = k² (h - y)², where k is the slope of its side.
+        let h = self.height as f64;
+        let k2 = (self.radius as f64 / h).powi(2);
+        let roots = solve_quadratic(
+            dx * dx + dz * dz - k2 * dy * dy,
+            2.0 * (ox * dx + oz * dz + k2 * (h - oy) * dy),
+            ox * ox + oz * oz - k2 * (h - oy) * (h - oy),
+        );
+        let mut nearest =
+            first_root(ray, &roots, t_min, t_max, |p| (0.0..=self.height).contains(&p.y())).map(
+                |(t, p)| {
+                    let k2 = k2 as f32;
+                    let normal = Vec3::new(p.x(), k2 * (self.height - p.y()), p.z());
+                    let normal = match normal.length() {
+                        // The normal is undefined at the apex.
+                        length if length > 0.0 => normal / length,
+                        _ => Vec3::new(0.0, 1.0, 0.0),
+                    };
+                    (t, normal, (azimuth(&p), p.y() / self.height))
+                },
+            );
+        if self.capped {
+            hit_cap(ray, 0.0, self.radius, -1.0, t_min, t_max, &mut nearest);
+        }
+        let (t, normal, uv) = nearest?;
+        Some(hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(revolved_bounds(self.radius, 0.0, self.height))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl Paraboloid {
+    // The coefficient `a` of the surface x² + z² = a y.
+    fn coefficient(&self) -> f32 {
+        self.radius * self.radius / self.y_max
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let ([ox, oy, oz], [dx, dy, dz]) = (to_f64(&ray.origin), to_f64(&ray.direction));
+        let a = self.coefficient() as f64;
+        let roots = solve_quadratic(
+            dx * dx + dz * dz,
+            2.0 * (ox * dx + oz * dz) - a * dy,
+            ox * ox + oz * oz - a * oy,
+        );
+        let mut nearest =
+            first_root(ray, &roots, t_min, t_max, |p| (self.y_min..=self.y_max).contains(&p.y()))
+                .map(|(t, p)| {
+                    let normal =
+                        Vec3::new(2.0 * p.x(), -self.coefficient(), 2.0 * p.z()).normalized();
+                    (t, normal, (azimuth(&p), (p.y() - self.y_min) / (self.y_max - self.y_min)))
+                });
+        if self.capped {
+            hit_cap(ray, self.y_max, self.radius, 1.0, t_min, t_max, &mut nearest);
+            if self.y_min > 0.0 {
+                let radius = (self.coefficient() * self.y_min).sqrt();
+                hit_cap(ray, self.y_min, radius, -1.0, t_min, t_max, &mut nearest);
+            }
+        }
+        let (t, normal, uv) = nearest?;
+        Some(hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(revolved_bounds(self.radius, self.y_min, self.y_max))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl Hittable for Torus {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // Solve along a unit direction starting from the point of the ray that is closest to the
+        // center, which keeps the coefficients of the quartic small for distant rays.
+        let length = ray.direction.length() as f64;
+        let d = to_f64(&ray.direction).map(|c| c / length);
+        let o = to_f64(&ray.origin);
+        let offset = -(o[0] * d[0] + o[1] * d[1] + o[2] * d[2]);
+        let o = [0, 1, 2].map(|i| o[i] + offset * d[i]);
+
+        // Substituting the ray into (|p|² + R² - r²)² = 4 R² (x² + z²) gives a quartic in the
+        // distance along the ray.
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+        let (r2, four_major2) = (minor * minor, 4.0 * major * major);
+        let od = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let e = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] - major * major - r2;
+        let coefficients = [
+            1.0,
+            4.0 * od,
+            2.0 * e + 4.0 * od * od + four_major2 * d[1] * d[1],
+            4.0 * od * e + 2.0 * four_major2 * o[1] * d[1],
+            e * e - four_major2 * (r2 - o[1] * o[1]),
+        ];
+        let to_distance = |t: f32| t as f64 * length - offset;
+        let roots = polynomial_roots(&coefficients, to_distance(t_min), to_distance(t_max));
+        let roots: Roots = roots.iter().map(|s| (s + offset) / length).collect();
+        let (t, p) = first_root(ray, &roots, t_min, t_max, |_| true)?;
+
+        // The normal points away from the closest point on the center circle of the tube.
+        let rho = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let center = if rho > 0.0 {
+            Vec3::new(p.x(), 0.0, p.z()) * (self.major_radius / rho)
+        } else {
+            Vec3::new(self.major_radius, 0.0, 0.0)
+        };
+        let normal = (p - center).normalized();
+        let theta = p.y().atan2(rho - self.major_radius);
+        let uv = (azimuth(&p), theta.rem_euclid(2.0 * PI) / (2.0 * PI));
+        Some(hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (major, minor) = (self.major_radius.abs(), self.minor_radius.abs());
+        let extent = Vec3::new(major + minor, minor, major + minor);
+        Some(Aabb::new(&-extent, &extent))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+// The outward normal and texture coordinates of a hit, and its parameter along the ray.
+type SurfaceHit = (f32, Vec3, TexCoord);
+
+fn hit_record(
+    ray: &Ray,
+    t: f32,
+    outward_normal: &Vec3,
+    uv: TexCoord,
+    material: &MaterialRef,
+) -> HitRecord {
+    let (is_front_face, normal) = align_face_normal(ray, outward_normal);
+    HitRecord {
+        point: ray.at(t),
+        normal,
+        material: material.clone(),
+        t,
+        is_front_face,
+        uv,
+        object_id: 0,
+    }
+}
+
+// Returns the first of the ascending `roots` within the range of the ray whose point is accepted
+// by `accept`, together with that point.
+fn first_root(
+    ray: &Ray,
+    roots: &Roots,
+    t_min: f32,
+    t_max: f32,
+    accept: impl Fn(&Point3) -> bool,
+) -> Option<(f32, Point3)> {
+    roots
+        .iter()
+        .map(|t| t as f32)
+        .filter(|t| *t >= t_min && *t <= t_max)
+        .map(|t| (t, ray.at(t)))
+        .find(|(_, p)| accept(p))
+}
+
+// Intersects the ray with the disk of the given radius around the y axis at height `y`, whose
+// outward normal points along `side` times the y axis. Replaces `nearest` if the disk is closer.
+fn hit_cap(
+    ray: &Ray,
+    y: f32,
+    radius: f32,
+    side: f32,
+    t_min: f32,
+    t_max: f32,
+    nearest: &mut Option<SurfaceHit>,
+) {
+    let t = (y - ray.origin.y()) / ray.direction.y();
+    let t_max = nearest.as_ref().map_or(t_max, |hit| hit.0);
+    if !(t >= t_min && t <= t_max && t.is_finite()) {
+        return;
+    }
+    let p = ray.at(t);
+    if p.x() * p.x() + p.z() * p.z() <= radius * radius {
+        let uv = (0.5 + 0.5 * p.x() / radius, 0.5 + 0.5 * p.z() / radius);
+        *nearest = Some((t, Vec3::new(0.0, side, 0.0), uv));
+    }
+}
+
+// The U texture coordinate of a point, from its angle around the y axis.
+fn azimuth(p: &Point3) -> f32 {
+    ((-p.z()).atan2(p.x()) + PI) / (2.0 * PI)
+}
+
+fn revolved_bounds(radius: f32, y_min: f32, y_max: f32) -> Aabb {
+    let radius = radius.abs();
+    Aabb::new(&Point3::new(-radius, y_min, -radius), &Point3::new(radius, y_max, radius))
+}
+
+fn to_f64(v: &Vec3) -> [f64; 3] {
+    v.data.map(|c| c as f64)
+}
+
+// The real roots of a polynomial of degree 4 or less, in ascending order.
+#[derive(Default)]
+struct Roots {
+    values: [f64; 4],
+    len: usize,
+}
+
+impl Roots {
+    fn push(&mut self, root: f64) {
+        self.values[self.len] = root;
+        self.len += 1;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        self.values[..self.len].iter().copied()
+    }
+}
+
+impl FromIterator<f64> for Roots {
+    fn from_iter<I: IntoIterator<Item = f64>>(roots: I) -> Roots {
+        let mut result = Roots::default();
+        roots.into_iter().for_each(|root| result.push(root));
+        result
+    }
+}
+
+// Solves a x² + b x + c = 0 without the cancellation of the textbook formula.
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Roots {
+    let mut roots = Roots::default();
+    if a == 0.0 {
+        if b != 0.0 {
+            roots.push(-c / b);
+        }
+        return roots;
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return roots;
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        // Both b and c are zero.
+        roots.push(0.0);
+        return roots;
+    }
+    let (t0, t1) = (q / a, c / q);
+    roots.push(t0.min(t1));
+    roots.push(t0.max(t1));
+    roots
+}
+
+// Finds the real roots of a polynomial between `lo` and `hi`, given its coefficients from the
+// highest degree down. The roots of the derivative split the range into intervals on which the
+// polynomial is monotonic, so each interval contains at most one root, which is found by
+// bisection. Roots of even multiplicity, where the sign doesn't change, may be missed.
+fn polynomial_roots(coefficients: &[f64], lo: f64, hi: f64) -> Roots {
+    let degree = coefficients.len() - 1;
+    if degree <= 2 {
+        let [a, b, c] = match coefficients {
+            [a, b, c] => [*a, *b, *c],
+            [b, c] => [0.0, *b, *c],
+            _ => return Roots::default(),
+        };
+        return solve_quadratic(a, b, c).iter().filter(|x| (lo..=hi).contains(x)).collect();
+    }
+
+    // All roots are within Cauchy's bound.
+    let bound =
+        1.0 + coefficients[1..].iter().map(|c| (c / coefficients[0]).abs()).fold(0.0, f64::max);
+    let (lo, hi) = (lo.max(-bound), hi.min(bound));
+    if lo > hi {
+        return Roots::default();
+    }
+
+    let mut derivative = [0.0; 4];
+    for (i, c) in coefficients[..degree].iter().enumerate() {
+        derivative[i] = c * (degree - i) as f64;
+    }
+    let critical_points = polynomial_roots(&derivative[..degree], lo, hi);
+
+    let evaluate = |x: f64| coefficients.iter().fold(0.0, |sum, c| sum * x + c);
+    let mut roots = Roots::default();
+    let mut start = lo;
+    for end in critical_points.iter().chain(std::iter::once(hi)) {
+        if let Some(root) = bisect(&evaluate, start, end) {
+            roots.push(root);
+        }
+        start = end;
+    }
+    roots
+}
+
+// Finds the root of a monotonic function in (lo, hi], if there is one.
+fn bisect(f: &impl Fn(f64) -> f64, mut lo: f64, mut hi: f64) -> Option<f64> {
+    let (f_lo, f_hi) = (f(lo), f(hi));
+    if f_hi == 0.0 {
+        return Some(hi);
+    }
+    if f_lo == 0.0 || f_lo.signum() == f_hi.signum() {
+        return None;
+    }
+    let increasing = f_hi > 0.0;
+    loop {
+        let mid = 0.5 * (lo + hi);
+        if mid <= lo || mid >= hi {
+            return Some(mid);
+        }
+        let value = f(mid);
+        if value == 0.0 {
+            return Some(mid);
+        }
+        if (value > 0.0) == increasing {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::RgbFloat, material::Lambertian, random::Rng};
+
+    // A point on a surface with its outward normal and texture coordinates.
+    type SurfacePoint = (Point3, Vec3, TexCoord);
+
+    // Samples points on the surface of `shape` through `surface`, which maps (u, v) to a point,
+    // and checks that rays aimed at them from nearby find them, and that rays from far away don't
+    // pass through the surface in front of them.
+    fn check_surface(shape: &dyn Hittable, surface: impl Fn(f32, f32) -> SurfacePoint) {
+        let rng = Rng::new(5);
+        let bounds = shape.bounding_box().unwrap();
+        for _ in 0..500 {
+            // Stay clear of the seam of the U coordinate.
+            let (u, v) = (0.02 + 0.96 * rng.random_float(), rng.random_float());
+            let (point, normal, uv) = surface(u, v);
+            assert!(bounds.hit(&ray(point, normal), -1e-3, 1e-3), "{:?}", point.data);
+
+            // From just outside, at an angle.
+            let tangent = normal.cross(&rng.sample_sphere()).normalized();
+            let origin = point + 0.01 * (normal + 0.7 * tangent).normalized();
+            let hit = shape.hit(&ray(origin, point - origin)).unwrap();
+            assert!((hit.point - point).length() < 1e-3, "{:?} {:?}", hit.point.data, point.data);
+            assert!(hit.is_front_face && (hit.normal - normal).length() < 1e-3);
+            assert!((hit.uv.0 - uv.0).abs() < 1e-3 && (hit.uv.1 - uv.1).abs() < 1e-3);
+
+            // From just inside.
+            let hit = shape.hit(&ray(point - 0.01 * normal, normal)).unwrap();
+            assert!((hit.point - point).length() < 1e-3 && !hit.is_front_face);
+
+            // From far away on the outside, avoiding grazing angles, the first hit can't be behind
+            // the point.
+            let origin = point + 50.0 * (normal + 0.9 * rng.sample_sphere()).normalized();
+            let hit = shape.hit(&ray(origin, point - origin)).unwrap();
+            assert!(hit.t <= 1.0 + 1e-4, "{}", hit.t);
+        }
+    }
+
+    fn ray(origin: Point3, direction: Vec3) -> Ray {
+        Ray { origin, direction, time: 0.0 }
+    }
+
+    fn material() -> MaterialRef {
+        Lambertian::new(RgbFloat::gray(0.5))
+    }
+
+    // Maps U to the angle around the y axis and returns its cosine and sine.
+    fn around(u: f32) -> (f32, f32) {
+        let phi = 2.0 * PI * u - PI;
+        (phi.cos(), -phi.sin())
+    }
+
+    // Samples the disk of the given radius at height `y`, whose normal points along `side`.
+    fn cap(y: f32, radius: f32, side: f32) -> impl Fn(f32, f32) -> SurfacePoint {
+        move |u, v| {
+            let (cos, sin) = around(u);
+            let (x, z) = (0.95 * v.sqrt() * radius * cos, 0.95 * v.sqrt() * radius * sin);
+            let uv = (0.5 + 0.5 * x / radius, 0.5 + 0.5 * z / radius);
+            (Point3::new(x, y, z), Vec3::new(0.0, side, 0.0), uv)
+        }
+    }
+
+    #[test]
+    fn cylinders_match_their_surface() {
+        let cylinder =
+            Cylinder { radius: 0.5, y_min: -1.0, y_max: 2.0, capped: true, material: material() };
+        check_surface(&cylinder, |u, v| {
+            let (cos, sin) = around(u);
+            let y = -1.0 + 3.0 * (0.01 + 0.98 * v);
+            (Point3::new(0.5 * cos, y, 0.5 * sin), Vec3::new(cos, 0.0, sin), (u, (y + 1.0) / 3.0))
+        });
+        check_surface(&cylinder, cap(-1.0, 0.5, -1.0));
+        check_surface(&cylinder, cap(2.0, 0.5, 1.0));
+
+        // Uncapped cylinders are open at both ends.
+        let down = ray(Point3::new(0.1, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(&down).is_some());
+        assert!(Cylinder { capped: false, ..cylinder }.hit(&down).is_none());
+    }
+
+    #[test]
+    fn cones_match_their_surface() {
+        let cone = Cone { radius: 1.0, height: 2.0, capped: true, material: material() };
+        check_surface(&cone, |u, v| {
+            let (cos, sin) = around(u);
+            let y = 2.0 * (0.02 + 0.96 * v);
+            let r = 1.0 - y / 2.0;
+            let normal = Vec3::new(2.0 * cos, 1.0, 2.0 * sin).normalized();
+            (Point3::new(r * cos, y, r * sin), normal, (u, y / 2.0))
+        });
+        check_surface(&cone, cap(0.0, 1.0, -1.0));
+
+        // The other nappe of the double cone is cut off.
+        let across = ray(Point3::new(-5.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(cone.hit(&across).is_none());
+    }
+
+    #[test]
+    fn paraboloids_match_their_surface() {
+        let paraboloid =
+            Paraboloid { radius: 1.0, y_min: 0.5, y_max: 2.0, capped: true, material: material() };
+        check_surface(&paraboloid, |u, v| {
+            let (cos, sin) = around(u);
+            let y = 0.5 + 1.5 * (0.01 + 0.98 * v);
+            let r = (y / 2.0).sqrt();
+            let normal = Vec3::new(2.0 * r * cos, -0.5, 2.0 * r * sin).normalized();
+            (Point3::new(r * cos, y, r * sin), normal, (u, (y - 0.5) / 1.5))
+        });
+        check_surface(&paraboloid, cap(2.0, 1.0, 1.0));
+        check_surface(&paraboloid, cap(0.5, 0.5, -1.0));
+    }
+
+    #[test]
+    fn tori_match_their_surface() {
+        let torus = Torus { major_radius: 1.0, minor_radius: 0.25, material: material() };
+        check_surface(&torus, |u, v| {
+            let (cos, sin) = around(u);
+            let theta = 2.0 * PI * (0.01 + 0.98 * v);
+            let normal = Vec3::new(theta.cos() * cos, theta.sin(), theta.cos() * sin);
+            let center = Point3::new(cos, 0.0, sin);
+            (center + 0.25 * normal, normal, (u, 0.01 + 0.98 * v))
+        });
+
+        // Rays through the hole miss, even from far away.
+        let through = ray(Point3::new(0.0, 1000.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&through).is_none());
+        let grazing = ray(Point3::new(-1000.0, 0.2, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = torus.hit(&grazing).unwrap();
+        assert!((hit.point.x() + 1.0 + 0.15).abs() < 1e-3, "{:?}", hit.point.data);
+    }
+
+    #[test]
+    fn finds_roots_of_quartics() {
+        // (x + 2)(x - 0.5)(x - 1)(x - 3)
+        let coefficients = [1.0, -2.5, -4.0, 8.5, -3.0];
+        let roots: Vec<f64> =
+            polynomial_roots(&coefficients, f64::NEG_INFINITY, f64::INFINITY).iter().collect();
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([-2.0, 0.5, 1.0, 3.0]) {
+            assert!((root - expected).abs() < 1e-9, "{:?}", roots);
+        }
+        assert_eq!(polynomial_roots(&coefficients, 0.75, 2.0).iter().count(), 1);
+
+        // x⁴ + 1 has no real roots.
+        assert_eq!(polynomial_roots(&[1.0, 0.0, 0.0, 0.0, 1.0], -10.0, 10.0).iter().count(), 0);
+    }
+}
@@ -3,65 +3,154 @@
 // Use of this source code is governed by the MIT License described
 // in the LICENSE file.
 
-use crate::algebra::Vec3;
+use crate::{algebra::Vec3, sampler::Sampler};
 use {
-    rand::distributions::{Distribution, Uniform},
-    rand_distr::{UnitDisc, UnitSphere},
+    rand::{
+        distributions::{Distribution, Uniform},
+        seq::SliceRandom,
+        SeedableRng,
+    },
+    rand_chacha::ChaCha8Rng,
+    std::{
+        cell::{Cell, RefCell},
+        f32::consts::PI,
+        sync::Arc,
+    },
 };
 
+/// A seedable random number generator. The same seed always produces the same sequence of numbers
+/// on every platform, which makes renders reproducible.
+///
+/// A generator created with `Rng::from_sampler` draws the numbers of a pixel sample from a
+/// `Sampler` instead, one dimension at a time.
 pub struct Rng {
+    generator: RefCell<ChaCha8Rng>,
     uniform: Uniform<f32>,
+    sequence: Option<SampleSequence>,
+}
+
+// The dimensions of a single pixel sample.
+struct SampleSequence {
+    sampler: Arc<dyn Sampler>,
+    pixel: u64,
+    index: u32,
+    dimension: Cell<u32>,
 }
 
 impl Rng {
-    pub fn new() -> Rng {
-        Rng { uniform: Uniform::new_inclusive(0.0, 1.0) }
+    /// Creates a generator for sequential use, e.g. for generating a scene.
+    pub fn new(seed: u64) -> Rng {
+        Rng::from_generator(ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    /// Creates a generator for a single sample of a pixel. Every sample of every pixel gets its
+    /// own independent stream, so the rendered image doesn't depend on the order in which threads
+    /// process them. These streams are also independent of the one created by `Rng::new`.
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Rng {
+        let mut key = [0; 32];
+        key[..8].copy_from_slice(&seed.to_le_bytes());
+        key[8..16].copy_from_slice(&sample.to_le_bytes());
+        let mut generator = ChaCha8Rng::from_seed(key);
+        generator.set_stream(pixel);
+        Rng::from_generator(generator)
+    }
+
+    /// Creates a generator whose `random_float`, `random_2d`, `sample_disk` and `sample_sphere`
+    /// draw successive dimensions of a pixel sample from `sampler`, starting at dimension 0. The
+    /// other methods behave like those of a generator created with `Rng::for_sample`.
+    pub fn from_sampler(sampler: Arc<dyn Sampler>, seed: u64, pixel: u64, sample: u32) -> Rng {
+        let sequence = SampleSequence { sampler, pixel, index: sample, dimension: Cell::new(0) };
+        Rng { sequence: Some(sequence), ..Rng::for_sample(seed, pixel, sample as u64) }
+    }
+
+    fn from_generator(generator: ChaCha8Rng) -> Rng {
+        Rng {
+            generator: RefCell::new(generator),
+            uniform: Uniform::new_inclusive(0.0, 1.0),
+            sequence: None,
+        }
+    }
+
+    /// Continues the sample at the given dimension. This keeps the dimensions consumed by each
+    /// bounce of a path the same across samples, regardless of how many dimensions the previous
+    /// bounces used. Has no effect if the generator doesn't use a sampler.
+    pub fn set_dimension(&self, dimension: u32) {
+        if let Some(sequence) = &self.sequence {
+            sequence.dimension.set(dimension);
+        }
     }
 
     /// Return a random number from a uniform distribution between 0.0 and 1.0.
     pub fn random_float(&self) -> f32 {
-        let mut rng = rand::thread_rng();
-        self.uniform.sample(&mut rng)
+        match &self.sequence {
+            Some(s) => {
+                let dimension = s.dimension.replace(s.dimension.get() + 1);
+                s.sampler.sample_1d(s.pixel, s.index, dimension)
+            }
+            None => self.uniform.sample(&mut *self.generator.borrow_mut()),
+        }
+    }
+
+    /// Return a random point from a uniform distribution over the unit square. A sampler
+    /// distributes the two coordinates evenly as a pair.
+    pub fn random_2d(&self) -> [f32; 2] {
+        match &self.sequence {
+            Some(s) => {
+                let dimension = s.dimension.replace(s.dimension.get() + 2);
+                s.sampler.sample_2d(s.pixel, s.index, dimension)
+            }
+            None => [self.random_float(), self.random_float()],
+        }
     }
 
     /// Return a random vector from a uniform distribution between 0.0 and 1.0.
     pub fn random_vec3(&self) -> Vec3 {
-        let mut rng = rand::thread_rng();
-        Vec3::new(
-            self.uniform.sample(&mut rng),
-            self.uniform.sample(&mut rng),
-            self.uniform.sample(&mut rng),
-        )
+        Vec3::new(self.random_float(), self.random_float(), self.random_float())
     }
 
     /// Return a random vector with values within the given range.
-    pub fn random_vec3_in_range(min: f32, max: f32) -> Vec3 {
-        let mut rng = rand::thread_rng();
+    pub fn random_vec3_in_range(&self, min: f32, max: f32) -> Vec3 {
         let uniform = Uniform::new_inclusive(min, max);
-        Vec3::new(
-            uniform.sample(&mut rng),
-            uniform.sample(&mut rng),
-            uniform.sample(&mut rng),
-        )
+        let mut rng = self.generator.borrow_mut();
+        Vec3::new(uniform.sample(&mut *rng), uniform.sample(&mut *rng), uniform.sample(&mut *rng))
+    }
+
+    /// Shuffle the elements of `slice` into a random order.
+    pub fn shuffle<T>(&self, slice: &mut [T]) {
+        slice.shuffle(&mut *self.generator.borrow_mut());
     }
 
     /// Return a random point inside a unit disk centered at origin and on the XY plane.
-    pub fn sample_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        let p = UnitDisc.sample(&mut rng);
-        Vec3::new(p[0], p[1], 0.0)
+    pub fn sample_disk(&self) -> Vec3 {
+        // The concentric mapping from "A Low Distortion Map Between Disk and Square" (Shirley and
+        // Chiu 1997) preserves the stratification of the square.
+        let [x, y] = self.random_2d();
+        let (x, y) = (2.0 * x - 1.0, 2.0 * y - 1.0);
+        if x == 0.0 && y == 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let (r, theta) = if x.abs() > y.abs() {
+            (x, PI / 4.0 * (y / x))
+        } else {
+            (y, PI / 2.0 - PI / 4.0 * (x / y))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     /// Return a random point on the surface of a unit sphere centered at origin.
-    pub fn sample_sphere() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        Vec3 { data: UnitSphere.sample(&mut rng) }
+    pub fn sample_sphere(&self) -> Vec3 {
+        let [u, v] = self.random_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
     /// Return a random point on the urface of a unit hemisphere centered at origin, with its apex
     /// aligned towards the given normal.
-    pub fn sample_hemisphere(normal: &Vec3) -> Vec3 {
-        let vec = Rng::sample_sphere();
+    #[allow(dead_code)]
+    pub fn sample_hemisphere(&self, normal: &Vec3) -> Vec3 {
+        let vec = self.sample_sphere();
 
         // Flip the vector if its not in the same hemisphere as the normal.
         if vec.dot(normal) < 0.0 {
@@ -71,3 +160,34 @@ impl Rng {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let sequence = |rng: Rng| (0..16).map(|_| rng.random_float()).collect::<Vec<f32>>();
+        assert_eq!(sequence(Rng::new(7)), sequence(Rng::new(7)));
+        assert_ne!(sequence(Rng::new(7)), sequence(Rng::new(8)));
+        assert_eq!(sequence(Rng::for_sample(7, 3, 5)), sequence(Rng::for_sample(7, 3, 5)));
+        assert_ne!(sequence(Rng::for_sample(7, 3, 5)), sequence(Rng::for_sample(7, 4, 5)));
+        assert_ne!(sequence(Rng::for_sample(7, 3, 5)), sequence(Rng::for_sample(7, 3, 6)));
+        assert_ne!(sequence(Rng::for_sample(7, 3, 5)), sequence(Rng::for_sample(8, 3, 5)));
+    }
+
+    #[test]
+    fn sampler_dimensions() {
+        let sampler = crate::sampler::SamplerType::Sobol.build(7, 16, 8);
+        let rng = Rng::from_sampler(sampler.clone(), 7, 3, 5);
+        let x = rng.random_float();
+        let [y, z] = rng.random_2d();
+        assert_eq!(x, sampler.sample_1d(3, 5, 0));
+        assert_eq!([y, z], sampler.sample_2d(3, 5, 1));
+
+        rng.set_dimension(10);
+        assert_eq!(rng.random_float(), sampler.sample_1d(3, 5, 10));
+        assert!((rng.sample_sphere().length() - 1.0).abs() < 1e-5);
+        assert!(rng.sample_disk().length() <= 1.0 + 1e-6);
+    }
+}
@@ -4,65 +4,813 @@
 // in the LICENSE file.
 
 use crate::{
-    algebra::Ray,
+    algebra::{Aabb, Point3, Ray, Vec3},
+    aov::{AovHit, AovPixel, AovSample, Aovs},
+    bvh::build_bvh,
     camera::Camera,
     color::RgbFloat,
+    film::{Film, Pixel},
+    filter::Filter,
+    light::LightList,
+    medium::{media_transmittance, scatter_in_media, ConstantMedium, Fog},
     random::Rng,
-    scene::{Hittable, Scene},
+    sampler::{Sampler, SamplerType},
+    scene::{HitRecord, Hittable, Scene},
 };
 use {
-    image::{Rgb, RgbImage},
+    image::Rgb32FImage,
     rayon::prelude::*,
+    std::{
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    },
 };
 
-pub fn render_scene(
-    scene: &Scene,
-    camera: &Camera,
-    rng: &Rng,
-    depth: u32,
-    samples_per_pixel: u32,
-    img: &mut RgbImage,
-) {
-    let (width, height) = (img.width(), img.height());
-    img.enumerate_pixels_mut().collect::<Vec<(u32, u32, &mut Rgb<u8>)>>().into_par_iter().for_each(
-        |(col, row, pixel)| {
-            let mut pixel_color = RgbFloat::black();
-            for _ in 0..samples_per_pixel {
-                let u = ((col as f32) + rng.random_float()) / (width as f32 - 1.0);
-                let v = 1.0 - ((row as f32) + rng.random_float()) / (height as f32 - 1.0);
-                let ray = camera.ray(u, v);
-
-                // Add 1 for at least one recursion for the primary rays.
-                pixel_color += ray_color(&ray, &scene, &rng, depth);
+/// The radiance arriving along rays that escape the scene without hitting any objects.
+#[derive(Debug, Copy, Clone)]
+pub enum Background {
+    /// A vertical gradient that blends from `bottom` to `top` based on the ray direction.
+    Gradient { bottom: RgbFloat, top: RgbFloat },
+
+    /// A constant color. Use black for scenes that are lit only by emissive objects.
+    Solid(RgbFloat),
+}
+
+impl Background {
+    /// The blue-white sky from the first book.
+    pub fn sky() -> Background {
+        Background::Gradient { bottom: RgbFloat::white(), top: RgbFloat::new(0.5, 0.7, 1.0) }
+    }
+
+    fn color(&self, ray: &Ray) -> RgbFloat {
+        match self {
+            Background::Gradient { bottom, top } => {
+                let dir = ray.direction.normalized();
+                let t = 0.5 * (dir.y() + 1.0);
+                (1.0 - t) * bottom + t * top
             }
+            Background::Solid(color) => *color,
+        }
+    }
+}
 
-            // Divide the color by the number of samples and gamma-correct for gamma=2.0.
-            pixel_color /= samples_per_pixel as f32;
-            let pixel_color = RgbFloat::new(
-                pixel_color.r().sqrt(),
-                pixel_color.g().sqrt(),
-                pixel_color.b().sqrt(),
-            );
-            *pixel = pixel_color.into();
-        },
-    );
+/// Parameters that control the sampling of each pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderParams {
+    /// The maximum number of path segments traced for each sample, including the primary ray.
+    pub max_depth: u32,
+    pub samples_per_pixel: u32,
+
+    /// The number of samples added to every pixel in each pass over the image. The image is
+    /// complete at the end of each pass, so smaller passes give earlier previews.
+    pub samples_per_pass: u32,
+
+    /// Seeds the random numbers of every sample. Rendering the same scene with the same seed
+    /// produces the same image, regardless of the number of samples per pass.
+    pub seed: u64,
+
+    /// Generates the random numbers of each sample. The stratified sampler depends on
+    /// `samples_per_pixel`, the others produce the same samples for any sample count.
+    pub sampler: SamplerType,
+
+    /// Weighs the samples that contribute to each pixel.
+    pub filter: Filter,
+
+    /// Stops sampling pixels whose noise is below a threshold. With `None`, every pixel receives
+    /// `samples_per_pixel` samples. Otherwise `samples_per_pixel` is the maximum.
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+impl Default for RenderParams {
+    fn default() -> Self {
+        RenderParams {
+            max_depth: 50,
+            samples_per_pixel: 100,
+            samples_per_pass: 1,
+            seed: 0,
+            sampler: SamplerType::Independent,
+            filter: Filter::default(),
+            adaptive: None,
+        }
+    }
+}
+
+/// Parameters of adaptive sampling, which spends fewer samples on pixels that converge quickly
+/// (e.g. flat background) and more on noisy ones (e.g. caustics and glossy reflections).
+#[derive(Debug, Copy, Clone)]
+pub struct AdaptiveSampling {
+    /// The largest acceptable standard error of a pixel's luminance, relative to the luminance.
+    pub noise_threshold: f32,
+
+    /// The number of samples that every pixel receives before its noise is estimated. Too few
+    /// samples may underestimate the noise of pixels where most paths miss the light.
+    pub min_samples: u32,
+}
+
+impl AdaptiveSampling {
+    // Dark pixels are compared against this luminance rather than their own, so that noise that
+    // is invisible in the final image doesn't keep them sampling.
+    const MIN_LUMINANCE: f32 = 0.01;
+
+    /// Returns true if the pixel needs no more samples.
+    pub fn is_converged(&self, pixel: &Pixel) -> bool {
+        if pixel.count < self.min_samples {
+            return false;
+        }
+        let luminance = pixel.mean().luminance().max(Self::MIN_LUMINANCE);
+        pixel.standard_error().is_some_and(|error| error <= self.noise_threshold * luminance)
+    }
+}
+
+/// A snapshot of the progress of a render, reported after each row of pixels in each pass.
+#[derive(Debug, Copy, Clone)]
+pub struct Progress {
+    /// The number of samples taken so far, over all pixels.
+    pub completed_samples: u64,
+    pub total_samples: u64,
+
+    /// Time since the render started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The completed fraction of the image, between 0 and 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total_samples == 0 {
+            return 1.0;
+        }
+        self.completed_samples as f32 / self.total_samples as f32
+    }
+
+    /// Returns true once every pixel has all of its samples, or has converged if adaptive sampling
+    /// is enabled.
+    pub fn is_complete(&self) -> bool {
+        self.completed_samples >= self.total_samples
+    }
+
+    /// Estimates the remaining time from the average rate so far. Returns `None` until at least one
+    /// sample is complete.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.completed_samples == 0 {
+            return None;
+        }
+        let remaining = self.total_samples.saturating_sub(self.completed_samples);
+        Some(self.elapsed.mul_f64(remaining as f64 / self.completed_samples as f64))
+    }
+}
+
+/// Stops a render early when cancelled. Clones share the same state, so a clone can be handed to
+/// the renderer and cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The maximum number of camera rays per pixel that the auxiliary outputs are averaged over.
+pub const AOV_SAMPLES: u32 = 16;
+
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+type PassCallback = Box<dyn Fn(&Film, &Progress) + Send + Sync>;
+
+/// Renders a scene as seen by a camera into a framebuffer of linear radiance.
+///
+/// ```
+/// use cpu_renderer::{
+///     render::{RenderParams, Renderer},
+///     scenes::simple_scene,
+/// };
+///
+/// let (scene, camera, background) = simple_scene(16.0 / 9.0);
+/// let img = Renderer::new(scene, camera)
+///     .background(background)
+///     .params(RenderParams { samples_per_pixel: 4, ..Default::default() })
+///     .on_progress(|p| println!("{:.0}%", 100.0 * p.fraction()))
+///     .render(32, 18);
+/// assert_eq!(img.dimensions(), (32, 18));
+/// ```
+pub struct Renderer {
+    scene: Scene,
+    lights: LightList,
+    camera: Camera,
+    background: Background,
+    fog: Option<Fog>,
+    media: Vec<ConstantMedium>,
+    params: RenderParams,
+    on_progress: Option<ProgressCallback>,
+    on_pass: Option<PassCallback>,
+    cancellation: CancellationToken,
+}
+
+impl Renderer {
+    /// Creates a renderer with the sky background and default parameters. This finds the light
+    /// sources of `scene` and builds its bounding volume hierarchy.
+    pub fn new(scene: Scene, camera: Camera) -> Renderer {
+        // Record the index of each object in its hits, for the object ID AOV.
+        let scene = scene
+            .into_iter()
+            .enumerate()
+            .map(|(id, object)| {
+                Box::new(SceneObject { id: id as u32, object }) as Box<dyn Hittable>
+            })
+            .collect();
+        let (scene, lights) = LightList::extract(scene);
+        Renderer {
+            scene: build_bvh(scene),
+            lights,
+            camera,
+            background: Background::sky(),
+            fog: None,
+            media: Vec::new(),
+            params: RenderParams::default(),
+            on_progress: None,
+            on_pass: None,
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    pub fn background(self, background: Background) -> Renderer {
+        Renderer { background, ..self }
+    }
+
+    /// Fills the space between the objects of the scene with fog.
+    pub fn fog(self, fog: Fog) -> Renderer {
+        Renderer { fog: Some(fog), ..self }
+    }
+
+    /// Adds participating media, such as smoke, to the scene.
+    pub fn media(self, media: Vec<ConstantMedium>) -> Renderer {
+        Renderer { media, ..self }
+    }
+
+    pub fn params(self, params: RenderParams) -> Renderer {
+        Renderer { params, ..self }
+    }
+
+    /// Sets a callback that is invoked after each row of pixels is complete. The callback is
+    /// called from the rendering threads, possibly concurrently.
+    pub fn on_progress<F>(self, callback: F) -> Renderer
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        Renderer { on_progress: Some(Box::new(callback)), ..self }
+    }
+
+    /// Sets a callback that is invoked with the accumulated samples and the progress after each
+    /// pass over the image.
+    pub fn on_pass<F>(self, callback: F) -> Renderer
+    where
+        F: Fn(&Film, &Progress) + Send + Sync + 'static,
+    {
+        Renderer { on_pass: Some(Box::new(callback)), ..self }
+    }
+
+    /// Sets a token that stops the render once it is cancelled.
+    pub fn cancellation(self, cancellation: CancellationToken) -> Renderer {
+        Renderer { cancellation, ..self }
+    }
+
+    /// Renders an image with the given dimensions, which should match the aspect ratio of the
+    /// camera. Each pixel receives the average linear radiance of its samples.
+    ///
+    /// If the render is cancelled, the pixels that haven't received any samples are left black.
+    pub fn render(&self, width: u32, height: u32) -> Rgb32FImage {
+        let mut film = Film::new(width, height);
+        self.render_into(&mut film);
+        film.image()
+    }
+
+    /// Adds samples to `film` in passes until every pixel has `samples_per_pixel` samples, or has
+    /// converged if adaptive sampling is enabled. Since the random numbers of each sample only
+    /// depend on the seed, the pixel and the index of the sample, this produces the same result as
+    /// rendering all samples at once.
+    pub fn render_into(&self, film: &mut Film) {
+        let tracer = Tracer {
+            scene: &self.scene,
+            lights: &self.lights,
+            background: &self.background,
+            fog: self.fog.as_ref(),
+            media: &self.media,
+            max_depth: self.params.max_depth,
+        };
+        let (width, height) = (film.width(), film.height());
+        let samples_per_pixel = self.params.samples_per_pixel;
+        let sampler = self.params.sampler.build(self.params.seed, samples_per_pixel, width);
+        let samples_per_pass = self.params.samples_per_pass.max(1);
+        let is_done = |pixel: &Pixel| {
+            pixel.count >= samples_per_pixel
+                || self.params.adaptive.is_some_and(|adaptive| adaptive.is_converged(pixel))
+        };
+        let start = Instant::now();
+        let total_samples = width as u64 * height as u64 * samples_per_pixel as u64;
+
+        // The samples that converged pixels skip count as complete, so that the progress reaches
+        // the total.
+        let completed_samples = AtomicU64::new(
+            film.pixels()
+                .iter()
+                .map(|p| if is_done(p) { samples_per_pixel } else { p.count } as u64)
+                .sum(),
+        );
+
+        while !self.cancellation.is_cancelled() {
+            // Bring every pixel that isn't done up to the next multiple of the pass size.
+            let min_samples = film.pixels().iter().filter(|p| !is_done(p)).map(|p| p.count).min();
+            let min_samples = match min_samples {
+                Some(min_samples) => min_samples,
+                None => break,
+            };
+            let target = (min_samples / samples_per_pass + 1) * samples_per_pass;
+            let target = target.min(samples_per_pixel);
+            film.par_rows_mut().for_each(|(row, pixels, splats)| {
+                let mut added = 0;
+                for (col, pixel) in pixels.iter_mut().enumerate() {
+                    if self.cancellation.is_cancelled() {
+                        break;
+                    }
+                    if is_done(pixel) {
+                        continue;
+                    }
+                    // Convergence is checked after every sample so that the result doesn't depend
+                    // on the pass size.
+                    while pixel.count < target && !is_done(pixel) {
+                        let (x, y) = (col as u32, row);
+                        let (value, [dx, dy]) =
+                            self.sample_pixel(&tracer, &sampler, x, y, width, height, pixel.count);
+                        pixel.add_sample(value);
+                        splats.splat(x as f32 + dx, y as f32 + dy, &value, &self.params.filter);
+                        added += 1;
+                    }
+                    if is_done(pixel) {
+                        // Count the skipped samples of the pixel as complete.
+                        added += samples_per_pixel.saturating_sub(pixel.count) as u64;
+                    }
+                }
+
+                let completed = completed_samples.fetch_add(added, Ordering::Relaxed) + added;
+                if let Some(callback) = &self.on_progress {
+                    callback(&Progress {
+                        completed_samples: completed,
+                        total_samples,
+                        elapsed: start.elapsed(),
+                    });
+                }
+            });
+
+            if self.cancellation.is_cancelled() {
+                break;
+            }
+            if let Some(callback) = &self.on_pass {
+                let progress = Progress {
+                    completed_samples: completed_samples.load(Ordering::Relaxed),
+                    total_samples,
+                    elapsed: start.elapsed(),
+                };
+                callback(film, &progress);
+            }
+        }
+    }
+
+    /// Records the auxiliary outputs of an image with the given dimensions (see `Aov`). Each pixel
+    /// averages up to `AOV_SAMPLES` camera rays, which are the same as those of the first samples
+    /// of `render`. The samples are not filtered.
+    pub fn render_aovs(&self, width: u32, height: u32) -> Aovs {
+        let sampler =
+            self.params.sampler.build(self.params.seed, self.params.samples_per_pixel, width);
+        let samples = self.params.samples_per_pixel.clamp(1, AOV_SAMPLES);
+        let pixels = (0..width as u64 * height as u64)
+            .into_par_iter()
+            .map(|index| {
+                let (col, row) = ((index % width as u64) as u32, (index / width as u64) as u32);
+                let samples: Vec<AovSample> = (0..samples)
+                    .map(|sample| {
+                        let (ray, _, _) =
+                            self.camera_ray(&sampler, col, row, width, height, sample);
+                        self.scene.hit(&ray).map(|hit| AovHit {
+                            albedo: hit.material.albedo(&hit),
+                            normal: hit.normal,
+                            depth: hit.t * ray.direction.length(),
+                            object: hit.object_id,
+                            material: Arc::as_ptr(&hit.material) as usize,
+                        })
+                    })
+                    .collect();
+                AovPixel::from_samples(&samples)
+            })
+            .collect();
+        Aovs::new(width, height, pixels)
+    }
+
+    // Traces the sample with the given index through the pixel at the given column and row.
+    // Returns its radiance and its position within the pixel.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(
+        &self,
+        tracer: &Tracer,
+        sampler: &Arc<dyn Sampler>,
+        col: u32,
+        row: u32,
+        width: u32,
+        height: u32,
+        sample: u32,
+    ) -> (RgbFloat, [f32; 2]) {
+        let (ray, offset, rng) = self.camera_ray(sampler, col, row, width, height, sample);
+        (tracer.ray_color(&ray, self.params.max_depth, None, &rng), offset)
+    }
+
+    // Generates the camera ray of the sample with the given index through the pixel at the given
+    // column and row. Returns the ray, its position within the pixel and the random numbers of the
+    // rest of the sample.
+    fn camera_ray(
+        &self,
+        sampler: &Arc<dyn Sampler>,
+        col: u32,
+        row: u32,
+        width: u32,
+        height: u32,
+        sample: u32,
+    ) -> (Ray, [f32; 2], Rng) {
+        let pixel_index = row as u64 * width as u64 + col as u64;
+        let rng = Rng::from_sampler(sampler.clone(), self.params.seed, pixel_index, sample);
+        let [x, y] = rng.random_2d();
+        let u = ((col as f32) + x) / (width as f32 - 1.0);
+        let v = 1.0 - ((row as f32) + y) / (height as f32 - 1.0);
+        (self.camera.ray(u, v, &rng), [x, y], rng)
+    }
+}
+
+// A top-level object of the scene, which records its index in its hits.
+struct SceneObject {
+    id: u32,
+    object: Box<dyn Hittable>,
+}
+
+impl Hittable for SceneObject {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let hit = self.object.bounded_hit(ray, t_min, t_max)?;
+        Some(HitRecord { object_id: self.id, ..hit })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.object.is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        self.object.can_sample()
+    }
+
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        self.object.sample_direction(origin, rng)
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.object.direction_pdf(origin, direction)
+    }
+}
+
+struct Tracer<'a> {
+    scene: &'a Scene,
+    lights: &'a LightList,
+    background: &'a Background,
+    fog: Option<&'a Fog>,
+    media: &'a [ConstantMedium],
+    max_depth: u32,
 }
 
-fn ray_color(ray: &Ray, scene: &Scene, rng: &Rng, depth: u32) -> RgbFloat {
-    // If we've exceeded the ray bounce limit, no more light is gathered.
-    if depth == 0 {
-        return RgbFloat::black();
+impl Tracer<'_> {
+    // The sample dimensions used by the pixel jitter, lens and time.
+    const CAMERA_DIMENSIONS: u32 = 5;
+
+    // The sample dimensions reserved for each bounce: the first half for scattering and the second
+    // half for light sampling.
+    const BOUNCE_DIMENSIONS: u32 = 12;
+
+    // The dimension within each bounce that samples the distance to the next scattering event in
+    // the fog. It follows the dimensions used by the materials to scatter.
+    const FOG_DIMENSION: u32 = 3;
+
+    // The two dimensions within each bounce that sample the scattering event in the media.
+    const MEDIUM_DIMENSION: u32 = 4;
+
+    /// Estimates the radiance along `ray`. `bsdf_pdf` is the density with which the ray direction
+    /// was sampled by the BSDF at the previous bounce, or `None` if the ray is a primary ray or was
+    /// scattered by a singular BSDF. It is used to weigh emission that is also reachable through
+    /// direct light sampling.
+    fn ray_color(&self, ray: &Ray, depth: u32, bsdf_pdf: Option<f32>, rng: &Rng) -> RgbFloat {
+        // If we've exceeded the ray bounce limit, no more light is gathered.
+        if depth == 0 {
+            return RgbFloat::black();
+        }
+
+        let dimension =
+            Self::CAMERA_DIMENSIONS + (self.max_depth - depth) * Self::BOUNCE_DIMENSIONS;
+        let mut hit = self.scene.hit(ray);
+        if !self.media.is_empty() {
+            rng.set_dimension(dimension + Self::MEDIUM_DIMENSION);
+            hit = scatter_in_media(self.media, ray, hit, rng);
+        }
+        if let Some(fog) = self.fog {
+            rng.set_dimension(dimension + Self::FOG_DIMENSION);
+            hit = fog.scatter(ray, hit, rng);
+        }
+        let hit = match hit {
+            Some(hit) => hit,
+            // Paint the background if no objects were intersected.
+            None => return self.background.color(ray),
+        };
+
+        // Combine emission found by BSDF sampling with that found by light sampling at the previous
+        // bounce using multiple importance sampling.
+        let mut color = hit.material.emitted(&hit);
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            let light_pdf = self.lights.direction_pdf(&ray.origin, &ray.direction);
+            color = power_heuristic(bsdf_pdf, light_pdf) * color;
+        }
+
+        rng.set_dimension(dimension);
+        let (attenuation, scattered) = match hit.material.scatter(ray, &hit, rng) {
+            Some(s) => s,
+            None => return color,
+        };
+        let scattered_pdf = hit.material.eval(ray, &hit, &scattered.direction).map(|(_, pdf)| pdf);
+        if scattered_pdf.is_some() && !self.lights.is_empty() {
+            rng.set_dimension(dimension + Self::BOUNCE_DIMENSIONS / 2);
+            color += self.sample_lights(ray, &hit, rng);
+        }
+        color + attenuation * self.ray_color(&scattered, depth - 1, scattered_pdf, rng)
     }
 
-    if let Some(hit) = scene.hit(ray) {
-        return match hit.material.scatter(ray, &hit) {
+    // Estimates the direct lighting at a hit point by casting a shadow ray towards a randomly
+    // chosen light.
+    fn sample_lights(&self, ray: &Ray, hit: &HitRecord, rng: &Rng) -> RgbFloat {
+        let direction = match self.lights.sample_direction(&hit.point, rng) {
+            Some(d) => d,
+            None => return RgbFloat::black(),
+        };
+        let light_pdf = self.lights.direction_pdf(&hit.point, &direction);
+        let (bsdf, bsdf_pdf) = match hit.material.eval(ray, hit, &direction) {
+            Some(eval) => eval,
+            None => return RgbFloat::black(),
+        };
+        if light_pdf <= 0.0 || bsdf_pdf <= 0.0 {
+            return RgbFloat::black();
+        }
+
+        // The shadow ray may hit a different object than the sampled light. This is accounted for
+        // since the light density is the average over all lights. Fog and media attenuate the light
+        // by their transmittance.
+        let shadow_ray = Ray { origin: hit.point, direction, time: ray.time };
+        match self.scene.hit(&shadow_ray) {
             None => RgbFloat::black(),
-            Some((attenuation, ray)) => attenuation * ray_color(&ray, scene, rng, depth - 1),
+            Some(light) => {
+                let weight = power_heuristic(light_pdf, bsdf_pdf);
+                let distance = light.t * shadow_ray.direction.length();
+                let transmittance = self.fog.map_or(1.0, |fog| fog.transmittance(distance))
+                    * media_transmittance(self.media, &shadow_ray, light.t);
+                weight * transmittance / light_pdf * bsdf * light.material.emitted(&light)
+            }
+        }
+    }
+}
+
+// The power heuristic with an exponent of 2, for the density `pdf` of the sampling technique that
+// produced a sample and the density `other_pdf` of the other technique.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::CameraParams, filter::FilterKind, material::DiffuseLight, planar::Quad,
+        scenes::cornell_box,
+    };
+
+    #[test]
+    fn same_seed_renders_identical_images() {
+        let render = |seed| {
+            let (scene, camera, background) = cornell_box(1.0);
+            let params =
+                RenderParams { max_depth: 4, samples_per_pixel: 2, seed, ..Default::default() };
+            Renderer::new(scene, camera).background(background).params(params).render(12, 12)
+        };
+        assert_eq!(render(3), render(3));
+        assert_ne!(render(3), render(4));
+    }
+
+    fn small_renderer() -> Renderer {
+        let (scene, camera, background) = cornell_box(1.0);
+        let params = RenderParams {
+            max_depth: 4,
+            samples_per_pixel: 3,
+            samples_per_pass: 2,
+            ..Default::default()
+        };
+        Renderer::new(scene, camera).background(background).params(params)
+    }
+
+    #[test]
+    fn reports_progress_for_every_row() {
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        small_renderer().on_progress(move |p| sink.lock().unwrap().push(*p)).render(8, 6);
+
+        // Two passes over six rows.
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 12);
+        let last = reports.iter().max_by_key(|p| p.completed_samples).unwrap();
+        assert_eq!((last.completed_samples, last.total_samples), (144, 144));
+        assert_eq!(last.fraction(), 1.0);
+        assert_eq!(last.eta(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn cancelled_render_stops_early() {
+        let token = CancellationToken::new();
+        let renderer = small_renderer().cancellation(token.clone());
+        token.cancel();
+        let img = renderer.render(8, 6);
+        assert!(img.pixels().all(|p| p.0 == [0.0; 3]));
+    }
+
+    #[test]
+    fn render_cancelled_after_a_pass_keeps_its_samples() {
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let renderer = small_renderer().cancellation(token).on_pass(move |_, _| cancel.cancel());
+        let mut film = Film::new(8, 6);
+        renderer.render_into(&mut film);
+        assert!(film.pixels().iter().all(|p| p.count == 2));
+
+        let (scene, camera, background) = cornell_box(1.0);
+        let params = RenderParams { samples_per_pixel: 2, ..renderer.params };
+        let first_pass =
+            Renderer::new(scene, camera).background(background).params(params).render(8, 6);
+        assert_eq!(film.image(), first_pass);
+    }
+
+    #[test]
+    fn passes_match_single_pass_render() {
+        let passes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = passes.clone();
+        let progressive = small_renderer()
+            .on_pass(move |film, progress| {
+                sink.lock().unwrap().push((film.min_samples(), progress.is_complete()))
+            })
+            .render(8, 6);
+        assert_eq!(*passes.lock().unwrap(), vec![(2, false), (3, true)]);
+
+        let (scene, camera, background) = cornell_box(1.0);
+        let params = RenderParams {
+            max_depth: 4,
+            samples_per_pixel: 3,
+            samples_per_pass: 3,
+            ..Default::default()
         };
+        let single =
+            Renderer::new(scene, camera).background(background).params(params).render(8, 6);
+        assert_eq!(progressive, single);
     }
 
-    // Paint a blue-white gradient background if no objects were intersected.
-    let dir = ray.direction.normalized();
-    let t = 0.5 * (dir.y() + 1.0);
-    RgbFloat((1.0 - t) * RgbFloat::new(1.0, 1.0, 1.0).0 + t * RgbFloat::new(0.5, 0.7, 1.0).0)
+    #[test]
+    fn filtered_render_is_independent_of_pass_size() {
+        let render = |samples_per_pass| {
+            let (scene, camera, background) = cornell_box(1.0);
+            let params = RenderParams {
+                max_depth: 4,
+                samples_per_pixel: 4,
+                samples_per_pass,
+                filter: Filter::new(FilterKind::Mitchell),
+                ..Default::default()
+            };
+            Renderer::new(scene, camera).background(background).params(params).render(8, 6)
+        };
+        assert_eq!(render(1), render(3));
+    }
+
+    fn adaptive_renderer(samples_per_pass: u32) -> Renderer {
+        let (scene, camera, background) = crate::scenes::simple_scene(16.0 / 9.0);
+        let adaptive = AdaptiveSampling { noise_threshold: 0.02, min_samples: 8 };
+        let params = RenderParams {
+            max_depth: 8,
+            samples_per_pixel: 64,
+            samples_per_pass,
+            adaptive: Some(adaptive),
+            ..Default::default()
+        };
+        Renderer::new(scene, camera).background(background).params(params)
+    }
+
+    fn adaptive_film(samples_per_pass: u32) -> Film {
+        let mut film = Film::new(16, 9);
+        adaptive_renderer(samples_per_pass).render_into(&mut film);
+        film
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_converged_pixels() {
+        let film = adaptive_film(4);
+        let counts: Vec<u32> = film.pixels().iter().map(|p| p.count).collect();
+        assert!(counts.iter().all(|&count| (8..=64).contains(&count)));
+
+        // The sky in the top row converges early while the noisy ground needs more samples.
+        assert!(film.sample_count(8, 0) < 64);
+        assert!(counts.contains(&64));
+    }
+
+    // Converged pixels have fewer samples than the maximum, so only the progress tells whether a
+    // pass is the last one.
+    #[test]
+    fn adaptive_render_is_complete_after_the_last_pass() {
+        let passes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = passes.clone();
+        let mut film = Film::new(16, 9);
+        adaptive_renderer(16)
+            .on_pass(move |_, progress| sink.lock().unwrap().push(progress.is_complete()))
+            .render_into(&mut film);
+        assert!(film.min_samples() < 64);
+        assert_eq!(*passes.lock().unwrap(), vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn adaptive_sampling_is_independent_of_pass_size() {
+        let (a, b) = (adaptive_film(1), adaptive_film(7));
+        assert_eq!(a.image(), b.image());
+        assert_eq!(a.sample_heatmap(), b.sample_heatmap());
+    }
+
+    #[test]
+    fn aovs_describe_first_hits() {
+        let (scene, camera, background) = cornell_box(1.0);
+        let renderer = Renderer::new(scene, camera).background(background);
+        let aovs = renderer.render_aovs(16, 16);
+
+        // The green wall on the left is the first object. The back wall is the fifth and shares
+        // its white material with the ceiling, which appears first in the image.
+        let wall = aovs.pixel(1, 5);
+        assert_eq!(wall.object_id, 1);
+        assert!((wall.albedo.g() - 0.45).abs() < 1e-5);
+        let back = aovs.pixel(8, 5);
+        assert_eq!((back.object_id, back.material_id), (5, 1));
+        // The back wall is 1355 units in front of the camera, a little further off the center.
+        assert!((1355.0..1400.0).contains(&back.depth), "{}", back.depth);
+        assert!((back.normal.z() + 1.0).abs() < 1e-5);
+        let ceiling = aovs.pixel(8, 1);
+        assert_eq!((ceiling.object_id, ceiling.material_id), (4, 1));
+        assert_ne!(wall.material_id, back.material_id);
+    }
+
+    #[test]
+    fn fog_attenuates_light() {
+        // A light that fills the view from 10 units away, seen through black fog.
+        let light = DiffuseLight::new(RgbFloat::white());
+        let scene: Scene = vec![Box::new(Quad::new(
+            Point3::new(-50.0, -50.0, 10.0),
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::new(0.0, 100.0, 0.0),
+            light,
+        ))];
+        let camera = Camera::new(CameraParams {
+            origin: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, 1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            aspect_ratio: 1.0,
+            fov_y: 5_f32.to_radians(),
+            aperture: 0.0,
+            focus_distance: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        });
+        let params = RenderParams { samples_per_pixel: 256, ..Default::default() };
+        let img = Renderer::new(scene, camera)
+            .background(Background::Solid(RgbFloat::black()))
+            .fog(Fog::new(0.1, RgbFloat::black()))
+            .params(params)
+            .render(4, 4);
+        let mean = img.pixels().map(|p| p.0[0]).sum::<f32>() / 16.0;
+        assert!((mean - (-1.0f32).exp()).abs() < 0.03, "{}", mean);
+    }
 }
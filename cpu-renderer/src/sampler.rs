@@ -0,0 +1,577 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use std::{
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+/// Generates the random numbers of each pixel sample. A sample is a point in a high-dimensional
+/// unit hypercube: the first dimensions jitter the position within the pixel and on the lens, and
+/// the following ones are consumed by each bounce of the path. Samplers that distribute the
+/// samples of a pixel more evenly than independent random numbers reduce noise at the same sample
+/// count.
+///
+/// Values only depend on the pixel, the sample index and the dimension, so samples can be
+/// generated in any order and on any thread.
+pub trait Sampler: Send + Sync {
+    /// Returns the value of `dimension` for the sample with the given index, in [0, 1).
+    fn sample_1d(&self, pixel: u64, index: u32, dimension: u32) -> f32;
+
+    /// Returns the values of `dimension` and `dimension + 1`, which are distributed evenly as a
+    /// pair rather than only individually.
+    fn sample_2d(&self, pixel: u64, index: u32, dimension: u32) -> [f32; 2];
+}
+
+/// Selects one of the built-in samplers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SamplerType {
+    /// Independent uniform random numbers.
+    Independent,
+
+    /// Divides each dimension (or pair of dimensions) into one stratum per sample and jitters
+    /// the samples within them.
+    Stratified,
+
+    /// The Halton sequence, Owen-scrambled per pixel. Dimensions beyond the first 64 are
+    /// independent.
+    Halton,
+
+    /// Pairs of dimensions of the Sobol sequence, shuffled and Owen-scrambled per pixel and
+    /// dimension.
+    Sobol,
+
+    /// The same Sobol points in every pixel, offset by a blue-noise mask. The error of
+    /// neighboring pixels is anti-correlated, so the remaining noise is of high frequency, which
+    /// is less visible and easier to filter out.
+    BlueNoise,
+}
+
+impl SamplerType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerType::Independent => "independent",
+            SamplerType::Stratified => "stratified",
+            SamplerType::Halton => "halton",
+            SamplerType::Sobol => "sobol",
+            SamplerType::BlueNoise => "blue-noise",
+        }
+    }
+
+    /// Creates the sampler. `samples_per_pixel` is the number of samples that the stratified
+    /// sampler divides each dimension into; the other samplers don't depend on it. `width` is the
+    /// width of the image, which the blue-noise sampler needs to find neighboring pixels.
+    pub fn build(&self, seed: u64, samples_per_pixel: u32, width: u32) -> Arc<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Arc::new(Independent { seed }),
+            SamplerType::Stratified => {
+                Arc::new(Stratified { seed, samples_per_pixel: samples_per_pixel.max(1) })
+            }
+            SamplerType::Halton => Arc::new(Halton { seed }),
+            SamplerType::Sobol => Arc::new(Sobol { seed }),
+            SamplerType::BlueNoise => Arc::new(BlueNoise { seed, width: width.max(1) as u64 }),
+        }
+    }
+}
+
+impl FromStr for SamplerType {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "independent" => Ok(SamplerType::Independent),
+            "stratified" => Ok(SamplerType::Stratified),
+            "halton" => Ok(SamplerType::Halton),
+            "sobol" => Ok(SamplerType::Sobol),
+            "blue-noise" => Ok(SamplerType::BlueNoise),
+            _ => Err(
+                "sampler must be 'independent', 'stratified', 'halton', 'sobol' or 'blue-noise'",
+            ),
+        }
+    }
+}
+
+struct Independent {
+    seed: u64,
+}
+
+impl Sampler for Independent {
+    fn sample_1d(&self, pixel: u64, index: u32, dimension: u32) -> f32 {
+        to_float(hash(&[self.seed, pixel, index as u64, dimension as u64]))
+    }
+
+    fn sample_2d(&self, pixel: u64, index: u32, dimension: u32) -> [f32; 2] {
+        [self.sample_1d(pixel, index, dimension), self.sample_1d(pixel, index, dimension + 1)]
+    }
+}
+
+struct Stratified {
+    seed: u64,
+    samples_per_pixel: u32,
+}
+
+impl Stratified {
+    // Samples are assigned to strata in a random order that differs for every pixel, dimension and
+    // round of `samples_per_pixel` samples, so that the dimensions aren't correlated. Returns the
+    // stratum among `strata` and the jitter within it.
+    fn stratum(&self, pixel: u64, index: u32, dimension: u32, strata: u32) -> (u32, [f32; 2]) {
+        let n = self.samples_per_pixel;
+        let (round, index) = (index / n, index % n);
+        let key = hash(&[self.seed, pixel, round as u64, dimension as u64]);
+        let stratum = permute(index, strata, key as u32);
+        let jitter = hash(&[key, index as u64]);
+        (stratum, [to_float(jitter), to_float(jitter << 32)])
+    }
+}
+
+impl Sampler for Stratified {
+    fn sample_1d(&self, pixel: u64, index: u32, dimension: u32) -> f32 {
+        let n = self.samples_per_pixel;
+        let (stratum, [jitter, _]) = self.stratum(pixel, index, dimension, n);
+        ((stratum as f32 + jitter) / n as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    // Uses a grid of at least `samples_per_pixel` cells. If the number of samples isn't a square
+    // number, some randomly chosen cells are left empty.
+    fn sample_2d(&self, pixel: u64, index: u32, dimension: u32) -> [f32; 2] {
+        let n = self.samples_per_pixel;
+        let columns = (n as f32).sqrt().ceil() as u32;
+        let rows = n.div_ceil(columns);
+        let (cell, [jx, jy]) = self.stratum(pixel, index, dimension, columns * rows);
+        [
+            (((cell % columns) as f32 + jx) / columns as f32).min(ONE_MINUS_EPSILON),
+            (((cell / columns) as f32 + jy) / rows as f32).min(ONE_MINUS_EPSILON),
+        ]
+    }
+}
+
+struct Halton {
+    seed: u64,
+}
+
+impl Halton {
+    const PRIMES: [u32; 64] = [
+        2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89,
+        97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181,
+        191, 193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281,
+        283, 293, 307, 311,
+    ];
+}
+
+impl Sampler for Halton {
+    fn sample_1d(&self, pixel: u64, index: u32, dimension: u32) -> f32 {
+        let key = hash(&[self.seed, pixel, dimension as u64]);
+        match Halton::PRIMES.get(dimension as usize) {
+            Some(&base) => scrambled_radical_inverse(index, base, key),
+            None => to_float(hash(&[key, index as u64])),
+        }
+    }
+
+    fn sample_2d(&self, pixel: u64, index: u32, dimension: u32) -> [f32; 2] {
+        [self.sample_1d(pixel, index, dimension), self.sample_1d(pixel, index, dimension + 1)]
+    }
+}
+
+// Pads the first two dimensions of the Sobol sequence, which form a (0, 2)-sequence, to any number
+// of dimensions by shuffling the sample order of each pair independently, as described in
+// "Practical Hash-based Owen Scrambling" (Burley 2020).
+struct Sobol {
+    seed: u64,
+}
+
+impl Sampler for Sobol {
+    fn sample_1d(&self, pixel: u64, index: u32, dimension: u32) -> f32 {
+        let [x, _] = scrambled_sobol(index, hash(&[self.seed, pixel, dimension as u64]));
+        to_float_bits(x)
+    }
+
+    fn sample_2d(&self, pixel: u64, index: u32, dimension: u32) -> [f32; 2] {
+        scrambled_sobol(index, hash(&[self.seed, pixel, dimension as u64])).map(to_float_bits)
+    }
+}
+
+// Blue-noise dithered sampling, as described in "Distributing Monte Carlo Errors as a Blue Noise
+// in Screen Space by Permuting Pixel Seeds Between Frames" (Heitz and Belcour 2019): every pixel
+// uses the same scrambled Sobol points, rotated on the unit torus (Cranley-Patterson rotation) by
+// the value of a blue-noise mask that is tiled over the image. Each dimension reads the mask at a
+// different offset, so that the dimensions aren't correlated.
+//
+// The rotation keeps the points of a pixel evenly spread, but not stratified into the same
+// intervals as the Sobol sampler.
+struct BlueNoise {
+    seed: u64,
+    width: u64,
+}
+
+impl BlueNoise {
+    // Returns the value of the mask at the pixel, shifted by an offset selected by `key`, as a
+    // 32-bit fixed point fraction.
+    fn offset(&self, pixel: u64, key: u64) -> u32 {
+        let (x, y) = ((pixel % self.width) as usize, (pixel / self.width) as usize);
+        let (x, y) = (x + key as usize % MASK_SIZE, y + (key >> 16) as usize % MASK_SIZE);
+        let index = (y % MASK_SIZE) * MASK_SIZE + x % MASK_SIZE;
+        (blue_noise_mask()[index] as u32) << (32 - 2 * MASK_BITS)
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn sample_1d(&self, pixel: u64, index: u32, dimension: u32) -> f32 {
+        let key = hash(&[self.seed, dimension as u64]);
+        let [x, _] = scrambled_sobol(index, key);
+        to_float_bits(x.wrapping_add(self.offset(pixel, key)))
+    }
+
+    fn sample_2d(&self, pixel: u64, index: u32, dimension: u32) -> [f32; 2] {
+        let key = hash(&[self.seed, dimension as u64]);
+        let [x, y] = scrambled_sobol(index, key);
+        [
+            to_float_bits(x.wrapping_add(self.offset(pixel, key))),
+            to_float_bits(y.wrapping_add(self.offset(pixel, key >> 32))),
+        ]
+    }
+}
+
+// The blue-noise mask is a MASK_SIZE x MASK_SIZE tile that contains every value below
+// MASK_SIZE^2 once.
+const MASK_BITS: u32 = 6;
+const MASK_SIZE: usize = 1 << MASK_BITS;
+
+// Returns the blue-noise mask, which is generated once with the void-and-cluster method from
+// "The void-and-cluster method for dither array generation" (Ulichney 1993). The mask is a ranking
+// of its cells: thresholding it at any value selects a set of cells that are evenly spread, with
+// no clusters or large gaps, including across the edges of the tile.
+fn blue_noise_mask() -> &'static [u16] {
+    static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        let cells = MASK_SIZE * MASK_SIZE;
+        let mut pattern = VoidAndCluster::new();
+
+        // Start from a random set of a tenth of the cells, and move the point in the tightest
+        // cluster into the largest void until that doesn't change anything.
+        for cell in 0..cells {
+            if to_float(hash(&[cell as u64])) < 0.1 {
+                pattern.insert(cell);
+            }
+        }
+        loop {
+            let cluster = pattern.tightest_cluster();
+            pattern.remove(cluster);
+            let void = pattern.largest_void();
+            pattern.insert(void);
+            if void == cluster {
+                break;
+            }
+        }
+
+        // Rank the initial points by removing them from the tightest cluster first, then fill the
+        // remaining cells from the largest void first.
+        let mut mask = vec![0; cells];
+        let initial = pattern.clone();
+        for rank in (0..pattern.count).rev() {
+            let cluster = pattern.tightest_cluster();
+            pattern.remove(cluster);
+            mask[cluster] = rank as u16;
+        }
+        pattern = initial;
+        for rank in pattern.count..cells {
+            let void = pattern.largest_void();
+            pattern.insert(void);
+            mask[void] = rank as u16;
+        }
+        mask
+    })
+}
+
+// A binary pattern on the cells of the mask, which tracks how densely the points surround each
+// cell, with the distance measured across the edges of the tile.
+#[derive(Clone)]
+struct VoidAndCluster {
+    is_set: Vec<bool>,
+    density: Vec<f32>,
+    count: usize,
+}
+
+impl VoidAndCluster {
+    fn new() -> VoidAndCluster {
+        let cells = MASK_SIZE * MASK_SIZE;
+        VoidAndCluster { is_set: vec![false; cells], density: vec![0.0; cells], count: 0 }
+    }
+
+    fn insert(&mut self, cell: usize) {
+        self.is_set[cell] = true;
+        self.count += 1;
+        self.spread(cell, 1.0);
+    }
+
+    fn remove(&mut self, cell: usize) {
+        self.is_set[cell] = false;
+        self.count -= 1;
+        self.spread(cell, -1.0);
+    }
+
+    // Adds a Gaussian around `cell` to the density.
+    fn spread(&mut self, cell: usize, sign: f32) {
+        const SIGMA: f32 = 1.5;
+        let wrapped = |a: usize, b: usize| {
+            let d = (a as isize - b as isize).unsigned_abs();
+            d.min(MASK_SIZE - d) as f32
+        };
+        for (other, density) in self.density.iter_mut().enumerate() {
+            let dx = wrapped(cell % MASK_SIZE, other % MASK_SIZE);
+            let dy = wrapped(cell / MASK_SIZE, other / MASK_SIZE);
+            *density += sign * (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+
+    // The set cell with the highest density.
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    // The empty cell with the lowest density.
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, is_set: bool, is_better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best = None;
+        for (cell, density) in self.density.iter().enumerate() {
+            if self.is_set[cell] == is_set
+                && best.is_none_or(|best: usize| is_better(*density, self.density[best]))
+            {
+                best = Some(cell);
+            }
+        }
+        best.expect("the pattern is neither empty nor full")
+    }
+}
+
+// The largest f32 below 1.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// Mixes a list of values into a well-distributed 64-bit hash, using the finalizer of SplitMix64.
+fn hash(values: &[u64]) -> u64 {
+    let mut h = 0x9e3779b97f4a7c15u64;
+    for value in values {
+        h ^= value.wrapping_add(0x9e3779b97f4a7c15).wrapping_add(h << 6).wrapping_add(h >> 2);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+        h ^= h >> 31;
+    }
+    h
+}
+
+// Maps the upper bits of a hash to [0, 1).
+fn to_float(h: u64) -> f32 {
+    to_float_bits((h >> 32) as u32)
+}
+
+// Maps a 32-bit fixed point fraction to [0, 1).
+fn to_float_bits(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// Returns the element at position `index` of a random permutation of [0, len) selected by `key`,
+// from "Correlated Multi-Jittered Sampling" (Kensler 2013).
+fn permute(mut index: u32, len: u32, key: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        index ^= key;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= key >> 16;
+        index ^= (index & w) >> 4;
+        index ^= key >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= key >> 23;
+        index ^= (index & w) >> 1;
+        index = index.wrapping_mul(1 | key >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & w) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= w;
+        index ^= index >> 5;
+        if index < len {
+            return (index.wrapping_add(key)) % len;
+        }
+    }
+}
+
+// Mirrors the digits of `index` in the given base around the radix point, permuting each digit
+// based on `key` and the digits before it (Owen scrambling). Unlike a plain radical inverse, this
+// spreads the first few points evenly even when the base is much larger than the number of points.
+fn scrambled_radical_inverse(mut index: u32, base: u32, key: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let (mut value, mut scale, mut prefix) = (0.0, inverse_base, key);
+
+    // Scramble enough digits to cover the precision of an f32, including the leading zeros of
+    // `index`.
+    while scale > f32::EPSILON as f64 / 2.0 || index > 0 {
+        let digit = index % base;
+        value += permute(digit, base, prefix as u32) as f64 * scale;
+        prefix = hash(&[prefix, digit as u64]);
+        index /= base;
+        scale *= inverse_base;
+    }
+    (value as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Returns the first (`dimension == 0`) or second dimension of the Sobol sequence as a 32-bit fixed
+// point fraction.
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let (mut result, mut direction, mut index) = (0, 1u32 << 31, index);
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    result
+}
+
+// Returns the point of the first two dimensions of the Sobol sequence with the given index, after
+// shuffling the order of the points and Owen-scrambling both dimensions based on `key`.
+fn scrambled_sobol(index: u32, key: u64) -> [u32; 2] {
+    let index = nested_uniform_scramble(index, key as u32);
+    [
+        nested_uniform_scramble(sobol(index, 0), (key >> 32) as u32),
+        nested_uniform_scramble(sobol(index, 1), hash(&[key]) as u32),
+    ]
+}
+
+// A hash-based Owen scramble, which randomly permutes the digits of `x` in a way that preserves
+// the stratification of the Sobol sequence.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLER_TYPES: [SamplerType; 5] = [
+        SamplerType::Independent,
+        SamplerType::Stratified,
+        SamplerType::Halton,
+        SamplerType::Sobol,
+        SamplerType::BlueNoise,
+    ];
+
+    #[test]
+    fn samples_are_in_unit_interval() {
+        for sampler_type in SAMPLER_TYPES {
+            let sampler = sampler_type.build(1, 16, 16);
+            for index in 0..64 {
+                for dimension in [0, 1, 5, 70] {
+                    let [x, y] = sampler.sample_2d(3, index, dimension);
+                    let z = sampler.sample_1d(3, index, dimension);
+                    assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)), "{:?}", sampler_type);
+                }
+            }
+        }
+    }
+
+    // Checks that the first 16 samples of every pixel fall into separate cells of a 4x4 grid, and
+    // into separate 1/16 intervals in one dimension.
+    #[test]
+    fn samples_are_stratified() {
+        for sampler_type in [SamplerType::Stratified, SamplerType::Sobol] {
+            let sampler = sampler_type.build(1, 16, 16);
+            for (pixel, dimension) in [(0, 0), (5, 2), (9, 11)] {
+                let mut cells = [false; 16];
+                let mut intervals = [false; 16];
+                for index in 0..16 {
+                    let [x, y] = sampler.sample_2d(pixel, index, dimension);
+                    let cell = (4.0 * y) as usize * 4 + (4.0 * x) as usize;
+                    let interval = (16.0 * sampler.sample_1d(pixel, index, dimension)) as usize;
+                    assert!(!cells[cell] && !intervals[interval], "{:?}", sampler_type);
+                    cells[cell] = true;
+                    intervals[interval] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn halton_first_dimension_is_stratified() {
+        let sampler = SamplerType::Halton.build(1, 16, 16);
+        let mut intervals = [false; 16];
+        for index in 0..16 {
+            let interval = (16.0 * sampler.sample_1d(7, index, 0)) as usize;
+            assert!(!intervals[interval]);
+            intervals[interval] = true;
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_is_a_ranking() {
+        let mut values = blue_noise_mask().to_vec();
+        values.sort_unstable();
+        assert!(values.iter().enumerate().all(|(i, value)| *value as usize == i));
+    }
+
+    // Blue noise has no low frequencies, so every block of the mask should average close to the
+    // mean. For white noise, the average of an 8x8 block has a standard deviation of about 150.
+    #[test]
+    fn blue_noise_mask_has_no_clusters() {
+        let mask = blue_noise_mask();
+        let mut worst: f32 = 0.0;
+        for block_y in (0..MASK_SIZE).step_by(8) {
+            for block_x in (0..MASK_SIZE).step_by(8) {
+                let sum: u32 = (0..64)
+                    .map(|i| mask[(block_y + i / 8) * MASK_SIZE + block_x + i % 8] as u32)
+                    .sum();
+                worst = worst.max((sum as f32 / 64.0 - 2047.5).abs());
+            }
+        }
+        assert!(worst < 150.0, "{}", worst);
+    }
+
+    // The mask holds every value once, so the samples of the pixels of a tile with the same index
+    // are stratified across the tile.
+    #[test]
+    fn blue_noise_samples_are_stratified_across_a_tile() {
+        let sampler = SamplerType::BlueNoise.build(1, 16, MASK_SIZE as u32);
+        for (index, dimension) in [(0, 0), (3, 2), (10, 9)] {
+            let mut intervals = vec![false; MASK_SIZE * MASK_SIZE];
+            let mut y_intervals = vec![false; MASK_SIZE * MASK_SIZE];
+            for pixel in 0..(MASK_SIZE * MASK_SIZE) as u64 {
+                let [x, y] = sampler.sample_2d(pixel, index, dimension);
+                assert_eq!(x, sampler.sample_1d(pixel, index, dimension));
+                let interval = (x * intervals.len() as f32) as usize;
+                let y_interval = (y * intervals.len() as f32) as usize;
+                assert!(!intervals[interval] && !y_intervals[y_interval]);
+                intervals[interval] = true;
+                y_intervals[y_interval] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for len in [1, 5, 16, 100] {
+            let mut values: Vec<u32> = (0..len).map(|i| permute(i, len, 12345)).collect();
+            values.sort_unstable();
+            assert_eq!(values, (0..len).collect::<Vec<u32>>());
+        }
+    }
+}
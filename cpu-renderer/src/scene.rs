@@ -4,9 +4,12 @@
 // in the LICENSE file.
 
 use crate::{
-    algebra::{Point3, Ray, Vec3},
+    algebra::{Aabb, Point3, Ray, Vec3},
     material::MaterialRef,
+    random::Rng,
+    texture::TexCoord,
 };
+use std::{f32::consts::PI, sync::Arc};
 
 pub type Scene = Vec<Box<dyn Hittable>>;
 
@@ -29,20 +32,82 @@ pub struct HitRecord {
 
     /// True if the ray intersected the surface at its front side.
     pub is_front_face: bool,
+
+    /// Surface texture coordinates at the point of intersection.
+    pub uv: TexCoord,
+
+    /// The index of the top-level scene object that was hit. This is assigned by the renderer;
+    /// shapes set it to 0.
+    pub object_id: u32,
 }
 
 /// Ignore nearby intersections that are closer to the ray origin than this factor. This helps
 /// prevent self-intersections
-const EPSILON: f32 = 0.001;
+pub(crate) const EPSILON: f32 = 0.001;
 
-pub trait Hittable: Sync {
+pub trait Hittable: Send + Sync {
     /// Find and return the closest intersection point along the ray within [t_max, t_max].
     fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
 
+    /// Returns an axis-aligned box that encloses the object, or `None` if the object is unbounded.
+    fn bounding_box(&self) -> Option<Aabb>;
+
     /// Find and return the closest intersection point along the ray in front of its origin.
     fn hit(&self, ray: &Ray) -> Option<HitRecord> {
         self.bounded_hit(ray, EPSILON, f32::INFINITY)
     }
+
+    /// Returns true if the surface of the object emits light.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Returns true if `sample_direction` supports the object. Emissive objects that don't are
+    /// not sampled as lights and only contribute the light of the rays that hit them at random.
+    fn can_sample(&self) -> bool {
+        false
+    }
+
+    /// Samples a direction from `origin` towards a random point on the surface of the object. This
+    /// is used to sample light sources directly. Returns `None` if the object doesn't support
+    /// sampling or isn't visible from `origin`.
+    fn sample_direction(&self, _origin: &Point3, _rng: &Rng) -> Option<Vec3> {
+        None
+    }
+
+    /// Returns the probability density, with respect to solid angle, with which `sample_direction`
+    /// generates `direction` from `origin`.
+    fn direction_pdf(&self, _origin: &Point3, _direction: &Vec3) -> f32 {
+        0.0
+    }
+}
+
+/// Shared objects are hittable, which allows them to be referenced from both the scene and the
+/// list of light sources.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        (**self).bounded_hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn is_emissive(&self) -> bool {
+        (**self).is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        (**self).can_sample()
+    }
+
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        (**self).sample_direction(origin, rng)
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        (**self).direction_pdf(origin, direction)
+    }
 }
 
 /// The hittable trait is implemented for a dynamic list of hittables.
@@ -58,8 +123,15 @@ impl Hittable for Vec<Box<dyn Hittable + '_>> {
         }
         nearest_hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.iter().map(|entry| entry.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.union(&b?)))
+    }
 }
 
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point3,
 
@@ -72,43 +144,210 @@ pub struct Sphere {
 
 impl Hittable for Sphere {
     fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
+        hit_sphere(&self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(&(self.center - r), &(self.center + r)))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    // Samples the cone of directions subtended by the sphere, as described in section 12.3 of
+    // "Ray Tracing: The Rest of Your Life".
+    fn sample_direction(&self, origin: &Point3, rng: &Rng) -> Option<Vec3> {
+        let cos_theta_max = self.cos_theta_max(origin)?;
+        let w = (self.center - origin).normalized();
+        let (u, v) = orthonormal_basis(&w);
+
+        let [r1, r2] = rng.random_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        Some(phi.cos() * sin_theta * u + phi.sin() * sin_theta * v + z * w)
+    }
+
+    fn direction_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(c) => c,
+            None => return 0.0,
+        };
+        // Test against the sampled cone rather than intersecting the sphere, so that directions
+        // grazing its silhouette are not lost to rounding.
+        let w = (self.center - origin).normalized();
+        if direction.normalized().dot(&w) < cos_theta_max * (1.0 - f32::EPSILON) {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+}
 
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
+impl Sphere {
+    // Returns the cosine of the half-angle of the cone that the sphere subtends when viewed from
+    // `origin`, or `None` if `origin` is inside the sphere.
+    fn cos_theta_max(&self, origin: &Point3) -> Option<f32> {
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
             return None;
         }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+}
 
-        // Find the nearest root within the acceptable range.
-        let root = {
-            let sqrt_d = discriminant.sqrt();
-            let root = (-half_b - sqrt_d) / a;
-            if root >= t_min && root <= t_max {
-                root
-            } else {
-                let root = (-half_b + sqrt_d) / a;
-                if root < t_min || root > t_max {
-                    return None;
-                }
-                root
-            }
-        };
+/// A sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`. Outside
+/// of this interval, the sphere stays at the nearest end of its path.
+///
+/// Moving spheres are not sampled as light sources, since the time of a ray is not known when
+/// sampling a light. Emissive moving spheres are only found by rays that hit them at random.
+#[derive(Clone)]
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: MaterialRef,
+}
 
-        let point = ray.at(root);
-        let normal = (point - self.center) / self.radius;
-        let (is_front_face, normal) = align_face_normal(ray, &normal);
-        Some(HitRecord { point, normal, material: self.material.clone(), t: root, is_front_face })
+impl MovingSphere {
+    pub fn center(&self, time: f32) -> Point3 {
+        if self.time1 <= self.time0 {
+            return self.center0;
+        }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + t * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn bounded_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        hit_sphere(&self.center(ray.time), self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(&(self.center0 - r), &(self.center0 + r));
+        Some(start.union(&Aabb::new(&(self.center1 - r), &(self.center1 + r))))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
     }
 }
 
+// Intersects a ray with the sphere of the given center and radius.
+fn hit_sphere(
+    center: &Point3,
+    radius: f32,
+    material: &MaterialRef,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord> {
+    let oc = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.length_squared() - radius * radius;
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // Find the nearest root within the acceptable range.
+    let root = {
+        let sqrt_d = discriminant.sqrt();
+        let root = (-half_b - sqrt_d) / a;
+        if root >= t_min && root <= t_max {
+            root
+        } else {
+            let root = (-half_b + sqrt_d) / a;
+            if root < t_min || root > t_max {
+                return None;
+            }
+            root
+        }
+    };
+
+    let point = ray.at(root);
+    let normal = (point - center) / radius;
+    let uv = sphere_uv(&((point - center) / radius.abs()));
+    let (is_front_face, normal) = align_face_normal(ray, &normal);
+    Some(HitRecord {
+        point,
+        normal,
+        material: material.clone(),
+        t: root,
+        is_front_face,
+        uv,
+        object_id: 0,
+    })
+}
+
+// Returns the spherical texture coordinates of a point on the unit sphere centered at origin. U
+// increases with the angle around the Y axis starting from -X, and V goes from 0 at the bottom (-Y)
+// to 1 at the top (+Y).
+fn sphere_uv(p: &Point3) -> TexCoord {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// Returns two unit vectors that form an orthonormal basis together with the unit vector `w`.
+pub fn orthonormal_basis(w: &Vec3) -> (Vec3, Vec3) {
+    let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let v = w.cross(&a).normalized();
+    let u = w.cross(&v);
+    (u, v)
+}
+
 // Transform the given "outward" facing normal such that the result is against the direction of the
 // incident ray. Returns the transformed normal vector and whether or not the ray intersected the
 // surface from the front or back.
-fn align_face_normal(ray: &Ray, outward_normal: &Vec3) -> (bool, Vec3) {
+pub fn align_face_normal(ray: &Ray, outward_normal: &Vec3) -> (bool, Vec3) {
     let is_front = ray.direction.dot(outward_normal) <= 0.0;
     let normal = if is_front { *outward_normal } else { -outward_normal };
     (is_front, normal)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::RgbFloat, material::Lambertian};
+
+    #[test]
+    fn sphere_uv_wraps_around_the_y_axis() {
+        let uv = |x, y, z| sphere_uv(&Point3::new(x, y, z));
+        let near = |a: TexCoord, b: TexCoord| (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6;
+        assert!(near(uv(-1.0, 0.0, 0.0), (0.0, 0.5)) || near(uv(-1.0, 0.0, 0.0), (1.0, 0.5)));
+        assert!(near(uv(0.0, 0.0, 1.0), (0.25, 0.5)));
+        assert!(near(uv(1.0, 0.0, 0.0), (0.5, 0.5)));
+        assert!(near(uv(0.0, 0.0, -1.0), (0.75, 0.5)));
+        // V goes from the bottom to the top.
+        assert_eq!((uv(0.0, -1.0, 0.0).1, uv(0.0, 1.0, 0.0).1), (0.0, 1.0));
+    }
+
+    #[test]
+    fn sampled_sphere_directions_have_positive_density() {
+        // Directions towards the silhouette of a distant sphere must not be lost to rounding.
+        let sphere = Sphere {
+            center: Point3::new(3.0, 40.0, -7.0),
+            radius: 0.5,
+            material: Lambertian::new(RgbFloat::white()),
+        };
+        let origin = Point3::new(0.1, 0.2, 0.3);
+        let rng = Rng::new(2);
+        for _ in 0..10000 {
+            let direction = sphere.sample_direction(&origin, &rng).unwrap();
+            assert!(sphere.direction_pdf(&origin, &direction) > 0.0, "{:?}", direction.data);
+        }
+    }
+}
@@ -0,0 +1,958 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::{
+    algebra::{Point3, Vec3},
+    bvh::build_bvh,
+    camera::CameraParams,
+    color::RgbFloat,
+    instance::{Instance, TransformStep},
+    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, MaterialRef, Metal},
+    medium::{ConstantMedium, Fog},
+    mesh::Triangle,
+    obj::load_obj,
+    planar::{BoxShape, Disk, Plane, Quad},
+    quadric::{Cone, Cylinder, Paraboloid, Torus},
+    random::Rng,
+    render::Background,
+    scene::{Hittable, MovingSphere, Scene, Sphere},
+    texture::{Checker, Image, Noise, SolidColor, TextureRef},
+};
+use {
+    anyhow::{anyhow, Context, Result},
+    serde::Deserialize,
+    std::{collections::BTreeMap, fs, path::Path, sync::Arc},
+};
+
+/// A scene loaded from a JSON scene description. See `SceneDesc` for the format.
+pub struct SceneFile {
+    pub scene: Scene,
+
+    /// The camera parameters. The `aspect_ratio` field is a placeholder; it is up to the caller to
+    /// set it to match the final image dimensions.
+    pub camera: CameraParams,
+
+    pub background: Background,
+    pub fog: Option<Fog>,
+
+    /// The `constant_medium` objects, which are rendered separately from the scene (see
+    /// `Renderer::media`).
+    pub media: Vec<ConstantMedium>,
+
+    pub settings: RenderSettings,
+}
+
+/// Render settings that can be specified by a scene file. Each one of these can be overridden on
+/// the command line.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSettings {
+    pub samples_per_pixel: Option<u32>,
+    pub max_bounces: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// Top-level structure of a scene file. An example:
+///
+/// ```json
+/// {
+///   "render": { "samples_per_pixel": 100, "width": 800 },
+///   "camera": {
+///     "origin": [13, 2, 3], "look_at": [0, 0, 0], "fov_y": 20,
+///     "aperture": 0.1, "focus_distance": 10, "shutter_open": 0, "shutter_close": 1
+///   },
+///   "background": { "type": "solid", "color": [0, 0, 0] },
+///   "textures": {
+///     "checker": { "type": "checker", "scale": 1, "even": [0.2, 0.3, 0.1], "odd": "marble" },
+///     "marble": { "type": "noise", "scale": 4 }
+///   },
+///   "materials": {
+///     "ground": { "type": "lambertian", "albedo": "checker" },
+///     "glass": { "type": "dielectric", "index_of_refraction": 1.5 },
+///     "lamp": { "type": "diffuse_light", "emit": [4, 4, 4] },
+///     "smoke": { "type": "isotropic", "albedo": [0.8, 0.8, 0.8] }
+///   },
+///   "fog": { "density": 0.01, "albedo": [0.9, 0.9, 0.9] },
+///   "objects": [
+///     { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0], "material": "ground" },
+///     { "type": "sphere", "center": [0, 5, 0], "radius": 1, "material": "lamp" },
+///     { "type": "sphere", "center": { "from": [2, 1, 0], "to": [2, 1.5, 0] }, "radius": 0.5,
+///       "material": "ground" },
+///     { "type": "mesh", "path": "teapot.obj", "material": "glass" },
+///     { "type": "box", "corners": [[-3, 0, -1], [-2, 1, 0]], "material": "ground" },
+///     { "type": "quad", "corner": [3, 0, 0], "u": [1, 0, 0], "v": [0, 1, 0], "material": "lamp" },
+///     { "type": "disk", "center": [0, 3, -3], "normal": [0, 0, 1], "radius": 1,
+///       "material": "glass" },
+///     { "type": "cylinder", "radius": 0.5, "y_min": 0, "y_max": 2, "capped": true,
+///       "material": "ground" },
+///     { "type": "torus", "major_radius": 1, "minor_radius": 0.25, "material": "glass" },
+///     { "type": "constant_medium", "density": 0.5, "material": "smoke",
+///       "boundary": { "type": "sphere", "center": [0, 1, 3], "radius": 1, "material": "smoke" } },
+///     {
+///       "type": "instance",
+///       "object": { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "ground" },
+///       "transform": [
+///         { "scale": [2, 1, 1] },
+///         { "rotate": { "axis": [0, 1, 0], "degrees": 45 } },
+///         { "translate": [4, 1, 0] }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    render: RenderSettings,
+    camera: CameraDesc,
+
+    /// Defaults to the sky gradient.
+    background: Option<BackgroundDesc>,
+    fog: Option<FogDesc>,
+
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    objects: Vec<ObjectDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    origin: [f32; 3],
+    look_at: [f32; 3],
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+
+    /// Vertical field of view in degrees.
+    fov_y: f32,
+
+    #[serde(default)]
+    aperture: f32,
+
+    /// Defaults to the distance between `origin` and `look_at`.
+    focus_distance: Option<f32>,
+
+    /// The shutter interval. Animated objects move between times 0 and 1.
+    #[serde(default)]
+    shutter_open: f32,
+    #[serde(default)]
+    shutter_close: f32,
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Gradient { bottom: [f32; 3], top: [f32; 3] },
+    Solid { color: [f32; 3] },
+}
+
+/// A color given either as an RGB triple or as the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorSource {
+    Color([f32; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f32; 3],
+    },
+    Checker {
+        scale: f32,
+        even: ColorSource,
+        odd: ColorSource,
+    },
+    Noise {
+        scale: f32,
+    },
+
+    /// An image file, resolved relative to the scene file.
+    Image {
+        path: String,
+    },
+}
+
+/// Homogeneous fog between the objects of the scene, see `Fog`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FogDesc {
+    density: f32,
+    albedo: [f32; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: ColorSource,
+    },
+    Metal {
+        albedo: ColorSource,
+        fuzz: f32,
+    },
+    Dielectric {
+        index_of_refraction: f32,
+    },
+    DiffuseLight {
+        emit: [f32; 3],
+    },
+
+    /// The phase function of participating media.
+    Isotropic {
+        albedo: ColorSource,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: Animated<[f32; 3]>,
+        radius: f32,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f32; 3]; 3],
+        material: String,
+    },
+
+    /// A parallelogram spanned by the edges `u` and `v` from `corner`.
+    Quad {
+        corner: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        material: String,
+    },
+    Disk {
+        center: [f32; 3],
+        normal: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+
+    /// An infinite plane through `point`.
+    Plane {
+        point: [f32; 3],
+        normal: [f32; 3],
+        material: String,
+    },
+
+    /// An axis-aligned box with the given opposite corners.
+    Box {
+        corners: [[f32; 3]; 2],
+        material: String,
+    },
+
+    /// Surfaces of revolution around the y axis, see the `quadric` module. Use an instance to
+    /// place them elsewhere.
+    Cylinder {
+        radius: f32,
+        y_min: f32,
+        y_max: f32,
+        #[serde(default)]
+        capped: bool,
+        material: String,
+    },
+    Cone {
+        radius: f32,
+        height: f32,
+        #[serde(default)]
+        capped: bool,
+        material: String,
+    },
+    Paraboloid {
+        radius: f32,
+        #[serde(default)]
+        y_min: f32,
+        y_max: f32,
+        #[serde(default)]
+        capped: bool,
+        material: String,
+    },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        material: String,
+    },
+
+    /// A Wavefront OBJ file, resolved relative to the scene file. The materials from the OBJ
+    /// file's material libraries are used unless `material` is specified.
+    Mesh {
+        path: String,
+        material: Option<String>,
+    },
+
+    /// A volume of constant density inside a convex boundary object, which scatters light
+    /// according to `material`, typically an isotropic one. The material of the boundary is
+    /// unused. Media can only be top-level objects; to move one, transform its boundary.
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: f32,
+        material: String,
+    },
+
+    /// Places another object with an affine transform, whose steps are applied in order. The
+    /// transform is animated if any step is.
+    Instance {
+        object: Box<ObjectDesc>,
+        transform: Vec<TransformDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate(Animated<[f32; 3]>),
+    Scale(Animated<[f32; 3]>),
+    Rotate { axis: [f32; 3], degrees: Animated<f32> },
+}
+
+/// A value that is either constant or moves linearly from `from` at time 0 to `to` at time 1.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum Animated<T> {
+    Constant(T),
+    Linear { from: T, to: T },
+}
+
+/// Loads and validates the scene file at `path`. Procedural textures draw their random numbers from
+/// `rng`.
+pub fn load_scene_file(path: &Path, rng: &Rng) -> Result<SceneFile> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("failed to read scene file \"{}\"", path.display()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&source, dir, rng).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+fn parse_scene(source: &str, dir: &Path, rng: &Rng) -> Result<SceneFile> {
+    // Report the path to the offending entry along with serde_json's line and column information.
+    let desc: SceneDesc =
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(source))
+            .map_err(|e| anyhow!("{}: {}", e.path(), e.inner()))?;
+
+    let camera = desc.camera.to_params().map_err(|e| anyhow!("camera: {}", e))?;
+    validate_settings(&desc.render).map_err(|e| anyhow!("render: {}", e))?;
+    let background = match &desc.background {
+        None => Background::sky(),
+        Some(background) => background.to_background().map_err(|e| anyhow!("background: {}", e))?,
+    };
+
+    let mut textures: BTreeMap<String, TextureRef> = BTreeMap::new();
+    for name in desc.textures.keys() {
+        build_texture(name, &desc.textures, dir, rng, &mut textures, 0)
+            .map_err(|e| anyhow!("textures.{}: {}", name, e))?;
+    }
+
+    let mut materials: BTreeMap<&str, MaterialRef> = BTreeMap::new();
+    for (name, material) in desc.materials.iter() {
+        let material =
+            material.to_material(&textures).map_err(|e| anyhow!("materials.{}: {}", name, e))?;
+        materials.insert(name, material);
+    }
+    let lookup = |name: &str| {
+        materials.get(name).cloned().ok_or_else(|| anyhow!("unknown material \"{}\"", name))
+    };
+
+    let (mut scene, mut media): (Scene, Vec<ConstantMedium>) = (Vec::new(), Vec::new());
+    for (i, object) in desc.objects.iter().enumerate() {
+        match object {
+            ObjectDesc::ConstantMedium { boundary, density, material } => {
+                build_medium(boundary, *density, material, dir, &lookup)
+                    .map(|medium| media.push(medium))
+            }
+            _ => object.instantiate(dir, &lookup, &mut scene),
+        }
+        .map_err(|e| anyhow!("objects[{}]: {}", i, e))?;
+    }
+    let fog =
+        desc.fog.as_ref().map(FogDesc::to_fog).transpose().map_err(|e| anyhow!("fog: {}", e))?;
+    Ok(SceneFile { scene, camera, background, fog, media, settings: desc.render })
+}
+
+impl CameraDesc {
+    fn to_params(&self) -> Result<CameraParams> {
+        let origin = to_vec3(self.origin);
+        let look_at = to_vec3(self.look_at);
+        let up = to_vec3(self.up);
+        let view = look_at - origin;
+        if view.length() == 0.0 {
+            return Err(anyhow!("origin and look_at must be different points"));
+        }
+        if view.cross(&up).length() == 0.0 {
+            return Err(anyhow!("up must not be parallel to the viewing direction"));
+        }
+        if !(self.fov_y > 0.0 && self.fov_y < 180.0) {
+            return Err(anyhow!("fov_y must be between 0 and 180 degrees"));
+        }
+        if self.aperture < 0.0 {
+            return Err(anyhow!("aperture must not be negative"));
+        }
+        let focus_distance = self.focus_distance.unwrap_or_else(|| view.length());
+        if focus_distance <= 0.0 {
+            return Err(anyhow!("focus_distance must be positive"));
+        }
+        if self.shutter_close < self.shutter_open {
+            return Err(anyhow!("shutter_close must not be earlier than shutter_open"));
+        }
+        Ok(CameraParams {
+            origin,
+            look_at,
+            up,
+            aspect_ratio: 1.0,
+            fov_y: self.fov_y.to_radians(),
+            aperture: self.aperture,
+            focus_distance,
+            shutter_open: self.shutter_open,
+            shutter_close: self.shutter_close,
+        })
+    }
+}
+
+fn validate_settings(settings: &RenderSettings) -> Result<()> {
+    if settings.width == Some(0) || settings.height == Some(0) {
+        return Err(anyhow!("image dimensions must be non-zero"));
+    }
+    if settings.samples_per_pixel == Some(0) {
+        return Err(anyhow!("samples_per_pixel must be non-zero"));
+    }
+    Ok(())
+}
+
+impl BackgroundDesc {
+    fn to_background(&self) -> Result<Background> {
+        Ok(match self {
+            BackgroundDesc::Gradient { bottom, top } => {
+                Background::Gradient { bottom: to_color(*bottom)?, top: to_color(*top)? }
+            }
+            BackgroundDesc::Solid { color } => Background::Solid(to_color(*color)?),
+        })
+    }
+}
+
+impl ColorSource {
+    fn to_texture(&self, textures: &BTreeMap<String, TextureRef>) -> Result<TextureRef> {
+        match self {
+            ColorSource::Color(color) => Ok(SolidColor::new(to_color(*color)?)),
+            ColorSource::Texture(name) => {
+                textures.get(name).cloned().ok_or_else(|| anyhow!("unknown texture \"{}\"", name))
+            }
+        }
+    }
+}
+
+// Builds the named texture and all textures it refers to, in dependency order. `depth` counts the
+// number of textures that are waiting for this one and is used to detect reference cycles.
+fn build_texture(
+    name: &str,
+    descs: &BTreeMap<String, TextureDesc>,
+    dir: &Path,
+    rng: &Rng,
+    textures: &mut BTreeMap<String, TextureRef>,
+    depth: usize,
+) -> Result<()> {
+    if textures.contains_key(name) {
+        return Ok(());
+    }
+    let desc = match descs.get(name) {
+        Some(desc) => desc,
+        None => return Err(anyhow!("unknown texture \"{}\"", name)),
+    };
+    if depth > descs.len() {
+        return Err(anyhow!("texture \"{}\" refers to itself", name));
+    }
+    let texture = match desc {
+        TextureDesc::Solid { color } => SolidColor::new(to_color(*color)?),
+        TextureDesc::Checker { scale, even, odd } => {
+            if *scale <= 0.0 {
+                return Err(anyhow!("scale must be positive"));
+            }
+            for source in [even, odd] {
+                if let ColorSource::Texture(dependency) = source {
+                    build_texture(dependency, descs, dir, rng, textures, depth + 1)?;
+                }
+            }
+            Checker::new(*scale, even.to_texture(textures)?, odd.to_texture(textures)?)
+        }
+        TextureDesc::Noise { scale } => Noise::new(*scale, rng),
+        TextureDesc::Image { path } => Image::load(&dir.join(path))?,
+    };
+    textures.insert(name.to_string(), texture);
+    Ok(())
+}
+
+impl FogDesc {
+    fn to_fog(&self) -> Result<Fog> {
+        if self.density <= 0.0 {
+            return Err(anyhow!("density must be positive"));
+        }
+        Ok(Fog::new(self.density, to_color(self.albedo)?))
+    }
+}
+
+impl MaterialDesc {
+    fn to_material(&self, textures: &BTreeMap<String, TextureRef>) -> Result<MaterialRef> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => {
+                Lambertian::textured(albedo.to_texture(textures)?)
+            }
+            MaterialDesc::Metal { albedo, fuzz } => {
+                if !(0.0..=1.0).contains(fuzz) {
+                    return Err(anyhow!("fuzz must be between 0 and 1"));
+                }
+                Metal::textured(albedo.to_texture(textures)?, *fuzz)
+            }
+            MaterialDesc::Dielectric { index_of_refraction } => {
+                if *index_of_refraction <= 0.0 {
+                    return Err(anyhow!("index_of_refraction must be positive"));
+                }
+                Dielectric::new(*index_of_refraction)
+            }
+            MaterialDesc::DiffuseLight { emit } => DiffuseLight::new(to_color(*emit)?),
+            MaterialDesc::Isotropic { albedo } => Isotropic::textured(albedo.to_texture(textures)?),
+        })
+    }
+}
+
+impl ObjectDesc {
+    fn instantiate(
+        &self,
+        dir: &Path,
+        lookup: &dyn Fn(&str) -> Result<MaterialRef>,
+        scene: &mut Scene,
+    ) -> Result<()> {
+        match self {
+            ObjectDesc::Sphere { center, radius, material } => {
+                if *radius == 0.0 {
+                    return Err(anyhow!("radius must be non-zero"));
+                }
+                let (radius, material) = (*radius, lookup(material)?);
+                match *center {
+                    Animated::Constant(center) => {
+                        scene.push(Box::new(Sphere { center: to_vec3(center), radius, material }))
+                    }
+                    Animated::Linear { from, to } => scene.push(Box::new(MovingSphere {
+                        center0: to_vec3(from),
+                        center1: to_vec3(to),
+                        time0: 0.0,
+                        time1: 1.0,
+                        radius,
+                        material,
+                    })),
+                }
+            }
+            ObjectDesc::Triangle { vertices, material } => {
+                let [a, b, c] = vertices.map(to_vec3);
+                if (b - a).cross(&(c - a)).length() == 0.0 {
+                    return Err(anyhow!("triangle is degenerate"));
+                }
+                scene.push(Box::new(Triangle::new([a, b, c], lookup(material)?)));
+            }
+            ObjectDesc::Quad { corner, u, v, material } => {
+                let (u, v) = (to_vec3(*u), to_vec3(*v));
+                if u.cross(&v).length() == 0.0 {
+                    return Err(anyhow!("quad is degenerate"));
+                }
+                scene.push(Box::new(Quad::new(to_vec3(*corner), u, v, lookup(material)?)));
+            }
+            ObjectDesc::Disk { center, normal, radius, material } => {
+                if *radius <= 0.0 {
+                    return Err(anyhow!("radius must be positive"));
+                }
+                let normal = to_normal(*normal)?;
+                scene.push(Box::new(Disk::new(
+                    to_vec3(*center),
+                    normal,
+                    *radius,
+                    lookup(material)?,
+                )));
+            }
+            ObjectDesc::Plane { point, normal, material } => {
+                let normal = to_normal(*normal)?;
+                scene.push(Box::new(Plane::new(to_vec3(*point), normal, lookup(material)?)));
+            }
+            ObjectDesc::Box { corners, material } => {
+                let [a, b] = corners.map(to_vec3);
+                if (0..3).any(|axis| a.data[axis] == b.data[axis]) {
+                    return Err(anyhow!("box must have a non-zero size along every axis"));
+                }
+                scene.push(Box::new(BoxShape::new(&a, &b, lookup(material)?)));
+            }
+            ObjectDesc::Cylinder { radius, y_min, y_max, capped, material } => {
+                validate_revolved(*radius, *y_min, *y_max)?;
+                scene.push(Box::new(Cylinder {
+                    radius: *radius,
+                    y_min: *y_min,
+                    y_max: *y_max,
+                    capped: *capped,
+                    material: lookup(material)?,
+                }));
+            }
+            ObjectDesc::Cone { radius, height, capped, material } => {
+                validate_revolved(*radius, 0.0, *height)?;
+                scene.push(Box::new(Cone {
+                    radius: *radius,
+                    height: *height,
+                    capped: *capped,
+                    material: lookup(material)?,
+                }));
+            }
+            ObjectDesc::Paraboloid { radius, y_min, y_max, capped, material } => {
+                if *y_min < 0.0 {
+                    return Err(anyhow!("y_min must not be negative"));
+                }
+                validate_revolved(*radius, *y_min, *y_max)?;
+                scene.push(Box::new(Paraboloid {
+                    radius: *radius,
+                    y_min: *y_min,
+                    y_max: *y_max,
+                    capped: *capped,
+                    material: lookup(material)?,
+                }));
+            }
+            ObjectDesc::Torus { major_radius, minor_radius, material } => {
+                if *major_radius <= 0.0 || *minor_radius <= 0.0 {
+                    return Err(anyhow!("radii must be positive"));
+                }
+                scene.push(Box::new(Torus {
+                    major_radius: *major_radius,
+                    minor_radius: *minor_radius,
+                    material: lookup(material)?,
+                }));
+            }
+            ObjectDesc::Mesh { path, material } => {
+                let material = material.as_deref().map(lookup).transpose()?;
+                scene.append(&mut load_obj(&dir.join(path), material)?);
+            }
+            ObjectDesc::ConstantMedium { .. } => {
+                return Err(anyhow!("constant_medium must be a top-level object"));
+            }
+            ObjectDesc::Instance { object, transform } => {
+                let steps =
+                    transform.iter().map(TransformDesc::to_step).collect::<Result<Vec<_>>>()?;
+                let mut objects: Scene = Vec::new();
+                object
+                    .instantiate(dir, lookup, &mut objects)
+                    .map_err(|e| anyhow!("object: {}", e))?;
+
+                // Objects that consist of several parts, such as meshes, are instanced as a whole.
+                let object: Arc<dyn Hittable> = match objects.len() {
+                    1 => Arc::from(objects.pop().unwrap()),
+                    _ => Arc::new(build_bvh(objects)),
+                };
+                let instance = Instance::animated(object, steps, 0.0, 1.0)
+                    .ok_or_else(|| anyhow!("transform is not invertible"))?;
+                scene.push(Box::new(instance));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Creates the medium of a top-level `constant_medium` object.
+fn build_medium(
+    boundary: &ObjectDesc,
+    density: f32,
+    material: &str,
+    dir: &Path,
+    lookup: &dyn Fn(&str) -> Result<MaterialRef>,
+) -> Result<ConstantMedium> {
+    if density <= 0.0 {
+        return Err(anyhow!("density must be positive"));
+    }
+    let mut objects: Scene = Vec::new();
+    boundary.instantiate(dir, lookup, &mut objects).map_err(|e| anyhow!("boundary: {}", e))?;
+    if objects.len() != 1 {
+        return Err(anyhow!("boundary must be a single object"));
+    }
+    Ok(ConstantMedium::new(objects.pop().unwrap(), density, lookup(material)?))
+}
+
+impl TransformDesc {
+    fn to_step(&self) -> Result<TransformStep> {
+        Ok(match self {
+            TransformDesc::Translate(offset) => {
+                TransformStep::Translate(offset.endpoints().map(to_vec3))
+            }
+            TransformDesc::Scale(factors) => {
+                let [start, end] = factors.endpoints();
+                if (0..3).any(|i| start[i] * end[i] <= 0.0) {
+                    return Err(anyhow!("transform is not invertible"));
+                }
+                TransformStep::Scale([to_vec3(start), to_vec3(end)])
+            }
+            TransformDesc::Rotate { axis, degrees } => {
+                let axis = to_vec3(*axis);
+                if axis.length() == 0.0 {
+                    return Err(anyhow!("rotation axis must be non-zero"));
+                }
+                let angles = degrees.endpoints().map(f32::to_radians);
+                TransformStep::Rotate { axis, angles }
+            }
+        })
+    }
+}
+
+impl<T: Copy> Animated<T> {
+    // Returns the values at times 0 and 1.
+    fn endpoints(&self) -> [T; 2] {
+        match *self {
+            Animated::Constant(value) => [value, value],
+            Animated::Linear { from, to } => [from, to],
+        }
+    }
+}
+
+fn validate_revolved(radius: f32, y_min: f32, y_max: f32) -> Result<()> {
+    if radius <= 0.0 {
+        return Err(anyhow!("radius must be positive"));
+    }
+    if y_min >= y_max {
+        return Err(anyhow!("the height range must not be empty"));
+    }
+    Ok(())
+}
+
+fn to_vec3(v: [f32; 3]) -> Vec3 {
+    Point3::new(v[0], v[1], v[2])
+}
+
+fn to_normal(n: [f32; 3]) -> Result<Vec3> {
+    let normal = to_vec3(n);
+    if normal.length() == 0.0 {
+        return Err(anyhow!("normal must be non-zero"));
+    }
+    Ok(normal)
+}
+
+fn to_color(c: [f32; 3]) -> Result<RgbFloat> {
+    if c.iter().any(|&v| v < 0.0) {
+        return Err(anyhow!("color components must not be negative"));
+    }
+    Ok(RgbFloat::new(c[0], c[1], c[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algebra::Ray;
+
+    const CAMERA: &str = r#""camera": { "origin": [0, 0, 1], "look_at": [0, 0, 0], "fov_y": 45 }"#;
+
+    fn error(source: &str) -> String {
+        parse_scene(source, Path::new(""), &Rng::new(0)).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_scene() {
+        let source = format!(
+            r#"{{
+                {},
+                "render": {{ "samples_per_pixel": 8 }},
+                "materials": {{
+                    "red": {{ "type": "lambertian", "albedo": [1, 0, 0] }},
+                    "mirror": {{ "type": "metal", "albedo": [1, 1, 1], "fuzz": 0 }},
+                    "smoke": {{ "type": "isotropic", "albedo": [0.5, 0.5, 0.5] }}
+                }},
+                "fog": {{ "density": 0.01, "albedo": [1, 1, 1] }},
+                "objects": [
+                    {{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "red" }},
+                    {{ "type": "triangle", "vertices": [[0, 0, 0], [1, 0, 0], [0, 1, 0]],
+                       "material": "mirror" }},
+                    {{ "type": "quad", "corner": [0, 0, 0], "u": [1, 0, 0], "v": [0, 1, 0],
+                       "material": "red" }},
+                    {{ "type": "disk", "center": [0, 0, 0], "normal": [0, 0, 1], "radius": 1,
+                       "material": "red" }},
+                    {{ "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0],
+                       "material": "red" }},
+                    {{ "type": "box", "corners": [[0, 0, 0], [1, 2, 3]], "material": "red" }},
+                    {{ "type": "cylinder", "radius": 1, "y_min": 0, "y_max": 1, "capped": true,
+                      "material": "red" }},
+                    {{ "type": "cone", "radius": 1, "height": 2, "material": "red" }},
+                    {{ "type": "paraboloid", "radius": 1, "y_max": 1, "material": "red" }},
+                    {{ "type": "torus", "major_radius": 1, "minor_radius": 0.5,
+                      "material": "red" }},
+                    {{ "type": "constant_medium", "density": 0.5, "material": "smoke",
+                      "boundary": {{ "type": "sphere", "center": [0, 0, 0], "radius": 1,
+                        "material": "smoke" }} }}
+                ]
+            }}"#,
+            CAMERA
+        );
+        let file = parse_scene(&source, Path::new(""), &Rng::new(0)).unwrap();
+        assert_eq!(file.scene.len(), 10);
+        assert_eq!(file.media.len(), 1);
+        assert!(file.scene[4].bounding_box().is_none());
+        assert!(file.fog.is_some());
+        assert_eq!(file.settings.samples_per_pixel, Some(8));
+        assert_eq!(file.settings.width, None);
+        assert_eq!(file.camera.focus_distance, 1.0);
+    }
+
+    #[test]
+    fn reports_offending_entry() {
+        let source = format!(
+            r#"{{ {}, "objects": [
+                {{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "red" }}
+            ] }}"#,
+            CAMERA
+        );
+        assert_eq!(error(&source), "objects[0]: unknown material \"red\"");
+
+        let source = format!(
+            r#"{{ {}, "materials": {{ "m": {{ "type": "metal", "albedo": [1, 1, 1] }} }},
+                "objects": [] }}"#,
+            CAMERA
+        );
+        assert!(error(&source).starts_with("materials.m: missing field `fuzz`"));
+
+        let source = format!(
+            r#"{{ {}, "materials": {{ "m": {{ "type": "metal", "albedo": [1, 1, 1], "fuzz": 2 }} }},
+                "objects": [] }}"#,
+            CAMERA
+        );
+        assert_eq!(error(&source), "materials.m: fuzz must be between 0 and 1");
+
+        let source = format!(
+            r#"{{ {}, "materials": {{ "m": {{ "type": "lambertian", "albedo": [1, 1, 1] }} }},
+                "objects": [{{ "type": "box", "corners": [[0, 0, 0], [1, 0, 1]], "material": "m" }}]
+            }}"#,
+            CAMERA
+        );
+        assert_eq!(error(&source), "objects[0]: box must have a non-zero size along every axis");
+
+        let source = format!(
+            r#"{{ {}, "materials": {{ "m": {{ "type": "lambertian", "albedo": [1, 1, 1] }} }},
+                "objects": [{{ "type": "cylinder", "radius": 1, "y_min": 1, "y_max": 1,
+                    "material": "m" }}]
+            }}"#,
+            CAMERA
+        );
+        assert_eq!(error(&source), "objects[0]: the height range must not be empty");
+
+        let source = format!(
+            r#"{{ {}, "materials": {{ "m": {{ "type": "isotropic", "albedo": [1, 1, 1] }} }},
+                "objects": [{{ "type": "instance", "transform": [{{ "translate": [1, 0, 0] }}],
+                    "object": {{ "type": "constant_medium", "density": 1, "material": "m",
+                        "boundary": {{ "type": "sphere", "center": [0, 0, 0], "radius": 1,
+                            "material": "m" }} }} }}]
+            }}"#,
+            CAMERA
+        );
+        assert_eq!(
+            error(&source),
+            "objects[0]: object: constant_medium must be a top-level object"
+        );
+    }
+
+    #[test]
+    fn transforms_instances() {
+        let instance = |transform: &str| {
+            format!(
+                r#"{{ {}, "materials": {{ "m": {{ "type": "lambertian", "albedo": [1, 1, 1] }} }},
+                    "objects": [{{ "type": "instance", "transform": [{}],
+                        "object": {{ "type": "sphere", "center": [0, 0, 0], "radius": 1,
+                                     "material": "m" }} }}] }}"#,
+                CAMERA, transform
+            )
+        };
+        // Steps apply in order: the sphere is stretched along x before being rotated onto z.
+        let source = instance(
+            r#"{ "scale": [2, 1, 1] }, { "rotate": { "axis": [0, 1, 0], "degrees": -90 } },
+               { "translate": [0, 0, -5] }"#,
+        );
+        let file = parse_scene(&source, Path::new(""), &Rng::new(0)).unwrap();
+        let bounds = file.scene[0].bounding_box().unwrap();
+        assert!((bounds.min.x() + 1.0).abs() < 1e-5 && (bounds.min.z() + 7.0).abs() < 1e-5);
+
+        // Small objects far from the origin are fine.
+        let source =
+            instance(r#"{ "scale": [0.01, 0.01, 0.01] }, { "translate": [100000, 0, 0] }"#);
+        let file = parse_scene(&source, Path::new(""), &Rng::new(0)).unwrap();
+        let bounds = file.scene[0].bounding_box().unwrap();
+        assert!((bounds.centroid().x() - 100000.0).abs() < 0.1);
+
+        assert_eq!(
+            error(&instance(r#"{ "scale": [1, 0, 1] }"#)),
+            "objects[0]: transform is not invertible"
+        );
+        assert_eq!(
+            error(&instance(r#"{ "scale": [1e-20, 1e-20, 1e-20] }"#)),
+            "objects[0]: transform is not invertible"
+        );
+        assert!(error(&instance(r#"{ "shear": 1 }"#)).contains("unknown variant `shear`"));
+    }
+
+    #[test]
+    fn animates_objects() {
+        let source = r#"{ "camera": { "origin": [0, 0, 1], "look_at": [0, 0, 0], "fov_y": 45,
+                              "shutter_open": 0, "shutter_close": 1 },
+                "materials": { "m": { "type": "lambertian", "albedo": [1, 1, 1] } },
+                "objects": [
+                    { "type": "sphere", "center": { "from": [0, 0, 0], "to": [4, 0, 0] },
+                       "radius": 1, "material": "m" },
+                    { "type": "instance",
+                       "transform": [{ "translate": { "from": [0, 4, 0], "to": [4, 4, 0] } }],
+                       "object": { "type": "sphere", "center": [0, 0, 0], "radius": 1,
+                                    "material": "m" } }
+                ] }"#;
+        let file = parse_scene(source, Path::new(""), &Rng::new(0)).unwrap();
+        assert_eq!((file.camera.shutter_open, file.camera.shutter_close), (0.0, 1.0));
+        let hits = |object: usize, x: f32, y: f32, time: f32| {
+            let ray =
+                Ray { origin: Point3::new(x, y, 10.0), direction: Vec3::new(0.0, 0.0, -1.0), time };
+            file.scene[object].hit(&ray).is_some()
+        };
+        for object in 0..2 {
+            let y = 4.0 * object as f32;
+            assert!(hits(object, 0.0, y, 0.0) && !hits(object, 0.0, y, 1.0));
+            assert!(hits(object, 2.0, y, 0.5) && hits(object, 4.0, y, 1.0));
+        }
+
+        let source = r#"{ "camera": { "origin": [0, 0, 1], "look_at": [0, 0, 0], "fov_y": 45,
+                              "shutter_open": 1, "shutter_close": 0 },
+                "objects": [] }"#;
+        assert_eq!(error(source), "camera: shutter_close must not be earlier than shutter_open");
+    }
+
+    #[test]
+    fn resolves_texture_references() {
+        let source = format!(
+            r#"{{ {}, "textures": {{
+                "a": {{ "type": "checker", "scale": 1, "even": "b", "odd": [0, 0, 0] }},
+                "b": {{ "type": "noise", "scale": 2 }}
+            }}, "materials": {{ "m": {{ "type": "lambertian", "albedo": "a" }} }},
+                "objects": [] }}"#,
+            CAMERA
+        );
+        assert!(parse_scene(&source, Path::new(""), &Rng::new(0)).is_ok());
+
+        let source = format!(
+            r#"{{ {}, "textures": {{
+                "a": {{ "type": "checker", "scale": 1, "even": "b", "odd": [0, 0, 0] }},
+                "b": {{ "type": "checker", "scale": 1, "even": "a", "odd": [0, 0, 0] }}
+            }}, "objects": [] }}"#,
+            CAMERA
+        );
+        assert!(error(&source).contains("refers to itself"));
+
+        let source = format!(
+            r#"{{ {}, "materials": {{ "m": {{ "type": "metal", "albedo": "x", "fuzz": 0 }} }},
+                "objects": [] }}"#,
+            CAMERA
+        );
+        assert_eq!(error(&source), "materials.m: unknown texture \"x\"");
+    }
+}
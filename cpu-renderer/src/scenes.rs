@@ -4,16 +4,18 @@
 // in the LICENSE file.
 
 use crate::{
-    algebra::Point3,
+    algebra::{Point3, Vec3},
     camera::{Camera, CameraParams},
     color::RgbFloat,
-    material::{Dielectric, Lambertian, Metal},
+    material::{Dielectric, DiffuseLight, Lambertian, Metal},
+    planar::Quad,
     random::Rng,
+    render::Background,
     scene::{Scene, Sphere},
 };
 
 /// The scene from the middle chapters with 3 spheres.
-pub fn simple_scene(aspect_ratio: f32) -> (Scene, Camera) {
+pub fn simple_scene(aspect_ratio: f32) -> (Scene, Camera, Background) {
     let mut scene: Scene = Vec::new();
 
     // Ground
@@ -64,12 +66,15 @@ pub fn simple_scene(aspect_ratio: f32) -> (Scene, Camera) {
             fov_y: 30_f32.to_radians(),
             aperture: 0.2,
             focus_distance: (focus - origin).length(),
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }),
+        Background::sky(),
     )
 }
 
 /// The final scene which is the cover of the book.
-pub fn cover_scene(rng: &Rng, aspect_ratio: f32) -> (Scene, Camera) {
+pub fn cover_scene(rng: &Rng, aspect_ratio: f32) -> (Scene, Camera, Background) {
     let mut scene: Scene = Vec::new();
 
     // Ground
@@ -89,10 +94,10 @@ pub fn cover_scene(rng: &Rng, aspect_ratio: f32) -> (Scene, Camera) {
             );
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 let material = if choose_material < 0.8 {
-                    let albedo = RgbFloat::random(&rng) * RgbFloat::random(&rng);
+                    let albedo = RgbFloat::random(rng) * RgbFloat::random(rng);
                     Lambertian::new(albedo)
                 } else if choose_material < 0.95 {
-                    let albedo = RgbFloat::random_in_range(0.5, 1.0);
+                    let albedo = RgbFloat::random_in_range(rng, 0.5, 1.0);
                     let fuzz = rng.random_float();
                     Metal::new(albedo, fuzz)
                 } else {
@@ -131,6 +136,67 @@ pub fn cover_scene(rng: &Rng, aspect_ratio: f32) -> (Scene, Camera) {
             fov_y: 20_f32.to_radians(),
             aperture: 0.1,
             focus_distance: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }),
+        Background::sky(),
+    )
+}
+
+/// The empty Cornell box from "Ray Tracing: The Next Week", lit only by the ceiling light, with a
+/// glass and a metal sphere inside. This scene is meant to be rendered with a square aspect ratio.
+pub fn cornell_box(aspect_ratio: f32) -> (Scene, Camera, Background) {
+    let mut scene: Scene = Vec::new();
+
+    let red = Lambertian::new(RgbFloat::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(RgbFloat::gray(0.73));
+    let green = Lambertian::new(RgbFloat::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(RgbFloat::gray(15.0));
+
+    // Walls, spanned by the edges of the box along each axis.
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    let x = Vec3::new(555.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 555.0, 0.0);
+    let z = Vec3::new(0.0, 0.0, 555.0);
+    scene.push(Box::new(Quad::new(x, y, z, green)));
+    scene.push(Box::new(Quad::new(origin, y, z, red)));
+    scene.push(Box::new(Quad::new(origin, x, z, white.clone())));
+    scene.push(Box::new(Quad::new(y + z, x, -z, white.clone())));
+    scene.push(Box::new(Quad::new(z, x, y, white)));
+
+    // Ceiling light
+    scene.push(Box::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+    )));
+
+    // Glass and metal spheres
+    scene.push(Box::new(Sphere {
+        center: Point3::new(190.0, 90.0, 190.0),
+        radius: 90.0,
+        material: Dielectric::new(1.5),
+    }));
+    scene.push(Box::new(Sphere {
+        center: Point3::new(370.0, 120.0, 370.0),
+        radius: 120.0,
+        material: Metal::new(RgbFloat::new(0.8, 0.85, 0.88), 0.0),
+    }));
+
+    (
+        scene,
+        Camera::new(CameraParams {
+            origin: Point3::new(278.0, 278.0, -800.0),
+            look_at: Point3::new(278.0, 278.0, 0.0),
+            up: Point3::new(0.0, 1.0, 0.0),
+            aspect_ratio,
+            fov_y: 40_f32.to_radians(),
+            aperture: 0.0,
+            focus_distance: 800.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }),
+        Background::Solid(RgbFloat::black()),
     )
 }
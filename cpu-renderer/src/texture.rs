@@ -0,0 +1,244 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+// The constructors below return shared, type-erased references rather than `Self`.
+#![allow(clippy::new_ret_no_self)]
+
+use crate::{
+    algebra::{Point3, Vec3},
+    color::{srgb_decode, RgbFloat},
+    random::Rng,
+};
+use {
+    anyhow::{Context, Result},
+    image::RgbImage,
+    std::{path::Path, sync::Arc},
+};
+
+type Ref<T> = Arc<Box<T>>;
+
+/// Texture coordinates in the [0, 1] range.
+pub type TexCoord = (f32, f32);
+
+pub trait Texture: Send + Sync {
+    /// Returns the color of the texture at the given texture coordinates and world-space point.
+    fn value(&self, uv: TexCoord, point: &Point3) -> RgbFloat;
+}
+
+pub type TextureRef = Ref<dyn Texture>;
+
+/// A texture with the same color everywhere.
+pub struct SolidColor {
+    color: RgbFloat,
+}
+
+impl SolidColor {
+    pub fn new(color: RgbFloat) -> TextureRef {
+        Arc::new(Box::new(SolidColor { color }))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: TexCoord, _: &Point3) -> RgbFloat {
+        self.color
+    }
+}
+
+/// A 3D checker pattern of cubes with side length `scale` that alternate between two textures.
+pub struct Checker {
+    scale: f32,
+    even: TextureRef,
+    odd: TextureRef,
+}
+
+impl Checker {
+    pub fn new(scale: f32, even: TextureRef, odd: TextureRef) -> TextureRef {
+        Arc::new(Box::new(Checker { scale, even, odd }))
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: TexCoord, point: &Point3) -> RgbFloat {
+        let sum: i64 = point.data.iter().map(|c| (c / self.scale).floor() as i64).sum();
+        if sum % 2 == 0 {
+            self.even.value(uv, point)
+        } else {
+            self.odd.value(uv, point)
+        }
+    }
+}
+
+/// A marble-like pattern generated from Perlin noise turbulence, as described in chapter 5 of
+/// "Ray Tracing: The Next Week".
+pub struct Noise {
+    perlin: Perlin,
+    scale: f32,
+}
+
+impl Noise {
+    /// Creates a noise texture whose random gradients are drawn from `rng`.
+    pub fn new(scale: f32, rng: &Rng) -> TextureRef {
+        Arc::new(Box::new(Noise { perlin: Perlin::new(rng), scale }))
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _: TexCoord, point: &Point3) -> RgbFloat {
+        let phase = self.scale * point.z() + 10.0 * self.perlin.turbulence(point, 7);
+        RgbFloat::gray(0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// A texture backed by an image file, mapped onto the surface using its texture coordinates.
+pub struct Image {
+    image: RgbImage,
+}
+
+impl Image {
+    /// Loads an image in any of the formats supported by the `image` crate.
+    pub fn load(path: &Path) -> Result<TextureRef> {
+        let image = image::open(path)
+            .with_context(|| format!("failed to load image \"{}\"", path.display()))?
+            .into_rgb8();
+        Ok(Arc::new(Box::new(Image { image })))
+    }
+}
+
+impl Texture for Image {
+    fn value(&self, uv: TexCoord, _: &Point3) -> RgbFloat {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return RgbFloat::black();
+        }
+
+        // Flip V to image coordinates, where the first row is at the top.
+        let u = uv.0.clamp(0.0, 1.0);
+        let v = 1.0 - uv.1.clamp(0.0, 1.0);
+        let col = ((u * width as f32) as u32).min(width - 1);
+        let row = ((v * height as f32) as u32).min(height - 1);
+        let pixel = self.image.get_pixel(col, row);
+
+        // Image files store sRGB-encoded colors.
+        let decode = |c: u8| srgb_decode(c as f32 / 255.0);
+        RgbFloat::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]))
+    }
+}
+
+const PERLIN_POINT_COUNT: usize = 256;
+
+/// Perlin noise generator with random gradient vectors.
+struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    fn new(rng: &Rng) -> Perlin {
+        let gradients = (0..PERLIN_POINT_COUNT)
+            .map(|_| rng.random_vec3_in_range(-1.0, 1.0).normalized())
+            .collect();
+        let permutation = || {
+            let mut p: Vec<usize> = (0..PERLIN_POINT_COUNT).collect();
+            rng.shuffle(&mut p);
+            p
+        };
+        Perlin { gradients, perm_x: permutation(), perm_y: permutation(), perm_z: permutation() }
+    }
+
+    /// Returns a smoothly varying value in [-1, 1].
+    fn noise(&self, p: &Point3) -> f32 {
+        let floor = p.data.map(|c| c.floor());
+        let frac = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        let cell = floor.map(|c| c as i64);
+
+        // Hermite-smoothed interpolation weights.
+        let smooth = frac.map(|f| f * f * (3.0 - 2.0 * f));
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((cell[0] + di) & 255) as usize]
+                        ^ self.perm_y[((cell[1] + dj) & 255) as usize]
+                        ^ self.perm_z[((cell[2] + dk) & 255) as usize];
+                    let (i, j, k) = (di as f32, dj as f32, dk as f32);
+                    let weight = Vec3::new(frac[0] - i, frac[1] - j, frac[2] - k);
+                    accum += (i * smooth[0] + (1.0 - i) * (1.0 - smooth[0]))
+                        * (j * smooth[1] + (1.0 - j) * (1.0 - smooth[1]))
+                        * (k * smooth[2] + (1.0 - k) * (1.0 - smooth[2]))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Sums `depth` octaves of noise with decreasing weights.
+    fn turbulence(&self, p: &Point3, depth: u32) -> f32 {
+        let mut accum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_between_cubes() {
+        let checker = Checker::new(
+            2.0,
+            SolidColor::new(RgbFloat::white()),
+            SolidColor::new(RgbFloat::black()),
+        );
+        let value = |x, y, z| checker.value((0.0, 0.0), &Point3::new(x, y, z)).r();
+        assert_eq!(value(0.5, 0.5, 0.5), 1.0);
+        assert_eq!(value(2.5, 0.5, 0.5), 0.0);
+        assert_eq!(value(2.5, 2.5, 0.5), 1.0);
+        // Cubes on the negative side of the origin continue the pattern.
+        assert_eq!(value(-0.5, 0.5, 0.5), 0.0);
+        assert_eq!(value(-0.5, -0.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn noise_is_bounded_and_reproducible() {
+        let (a, b) = (Noise::new(4.0, &Rng::new(7)), Noise::new(4.0, &Rng::new(7)));
+        let rng = Rng::new(1);
+        for _ in 0..100 {
+            let p = rng.random_vec3_in_range(-10.0, 10.0);
+            let value = a.value((0.0, 0.0), &p).r();
+            assert!((0.0..=1.0).contains(&value), "{}", value);
+            assert_eq!(value, b.value((0.0, 0.0), &p).r());
+        }
+    }
+
+    #[test]
+    fn images_are_sampled_with_v_pointing_up() {
+        // A 2x2 image with a red top-left and a blue bottom-right pixel.
+        let mut image = RgbImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        image.put_pixel(1, 1, image::Rgb([0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgb([128, 128, 128]));
+        let texture = Image { image };
+        let value = |u, v| texture.value((u, v), &Point3::new(0.0, 0.0, 0.0));
+
+        assert_eq!(value(0.25, 0.75).r(), 1.0);
+        assert_eq!(value(0.75, 0.25).b(), 1.0);
+        assert_eq!(value(0.25, 0.25).r(), 0.0);
+        // Colors are decoded from sRGB, and coordinates outside [0, 1] are clamped.
+        assert_eq!(value(0.75, 0.75).g(), srgb_decode(128.0 / 255.0));
+        assert_eq!(value(-1.0, 2.0).r(), 1.0);
+        assert_eq!(value(1.0, 0.0).b(), 1.0);
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (c) 2022 Arman Uguray
+//
+// Use of this source code is governed by the MIT License described
+// in the LICENSE file.
+
+use crate::color::RgbFloat;
+use {image::Rgb32FImage, std::str::FromStr};
+
+/// Compresses linear scene radiance into the [0, 1] range of a display.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ToneMapper {
+    /// Clips values above 1, which loses detail in bright areas such as light sources.
+    Clamp,
+
+    /// Maps luminance L to L / (1 + L), which never reaches white.
+    Reinhard,
+
+    /// Reinhard's operator extended with a white point, the luminance that maps to 1.
+    ExtendedReinhard,
+
+    /// A fit of the ACES reference rendering and sRGB output transforms by Stephen Hill, with a
+    /// filmic contrast curve and desaturated highlights.
+    Aces,
+
+    /// Troy Sobotka's AgX, which desaturates bright colors smoothly towards white instead of
+    /// shifting their hue.
+    Agx,
+}
+
+impl ToneMapper {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::ExtendedReinhard => "extended-reinhard",
+            ToneMapper::Aces => "aces",
+            ToneMapper::Agx => "agx",
+        }
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "extended-reinhard" => Ok(ToneMapper::ExtendedReinhard),
+            "aces" => Ok(ToneMapper::Aces),
+            "agx" => Ok(ToneMapper::Agx),
+            _ => {
+                Err("tone mapper must be 'clamp', 'reinhard', 'extended-reinhard', 'aces' or 'agx'")
+            }
+        }
+    }
+}
+
+/// The post-process that turns a framebuffer of linear radiance into displayable colors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneMapping {
+    pub tone_mapper: ToneMapper,
+
+    /// Scales the radiance by 2^exposure before tone mapping.
+    pub exposure: f32,
+
+    /// The luminance (after exposure) that the extended Reinhard operator maps to white. If not
+    /// given, the maximum luminance of the image is used.
+    pub white_point: Option<f32>,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping { tone_mapper: ToneMapper::Clamp, exposure: 0.0, white_point: None }
+    }
+}
+
+impl ToneMapping {
+    /// Returns the tone mapped image, with linear values between 0 and 1.
+    pub fn apply(&self, img: &Rgb32FImage) -> Rgb32FImage {
+        let scale = self.exposure.exp2();
+        let white = match self.white_point {
+            Some(white) => white,
+            None => img.pixels().map(|p| scale * RgbFloat::from(p).luminance()).fold(0.0, f32::max),
+        };
+        let mut result = img.clone();
+        for pixel in result.pixels_mut() {
+            let color = scale * RgbFloat::from(&*pixel);
+            let color = match self.tone_mapper {
+                ToneMapper::Clamp => color,
+                ToneMapper::Reinhard => reinhard(&color, None),
+                ToneMapper::ExtendedReinhard => reinhard(&color, Some(white)),
+                ToneMapper::Aces => aces(&color),
+                ToneMapper::Agx => agx(&color),
+            };
+            *pixel = clamp(&color).into();
+        }
+        result
+    }
+}
+
+fn clamp(color: &RgbFloat) -> RgbFloat {
+    // NaN is mapped to 0.
+    let clamp = |c: f32| if c > 0.0 { c.min(1.0) } else { 0.0 };
+    RgbFloat::new(clamp(color.r()), clamp(color.g()), clamp(color.b()))
+}
+
+// Applies the operator to the luminance and scales the color accordingly, which preserves its hue.
+fn reinhard(color: &RgbFloat, white: Option<f32>) -> RgbFloat {
+    let luminance = color.luminance();
+    if luminance <= 0.0 {
+        return RgbFloat::black();
+    }
+    let mapped = match white {
+        Some(white) if white > 0.0 => {
+            luminance * (1.0 + luminance / (white * white)) / (1.0 + luminance)
+        }
+        _ => luminance / (1.0 + luminance),
+    };
+    (mapped / luminance) * color
+}
+
+fn aces(color: &RgbFloat) -> RgbFloat {
+    // sRGB to the ACES rendering space, including the exposure bias of the reference transform.
+    const INPUT: [[f32; 3]; 3] =
+        [[0.59719, 0.35458, 0.04823], [0.07600, 0.90834, 0.01566], [0.02840, 0.13383, 0.83777]];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let rrt_and_odt =
+        |v: f32| (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081);
+    let [r, g, b] = transform(&INPUT, color);
+    let [r, g, b] =
+        transform(&OUTPUT, &RgbFloat::new(rrt_and_odt(r), rrt_and_odt(g), rrt_and_odt(b)));
+    RgbFloat::new(r, g, b)
+}
+
+// The minimal AgX implementation by Benjamin Wrensch, which approximates the base contrast curve
+// with a polynomial.
+fn agx(color: &RgbFloat) -> RgbFloat {
+    const INSET: [[f32; 3]; 3] = [
+        [0.8424791, 0.0784336, 0.07922375],
+        [0.04232824, 0.8784686, 0.07916613],
+        [0.04237565, 0.0784336, 0.879143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.05289685, 1.151903, -0.09896118],
+        [-0.05297164, -0.09804345, 1.151074],
+    ];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let curve = |c: f32| {
+        let x = (c.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+    let [r, g, b] = transform(&INSET, color);
+    let [r, g, b] = transform(&OUTSET, &RgbFloat::new(curve(r), curve(g), curve(b)));
+
+    // The curve produces display-encoded values with a gamma of 2.2.
+    let decode = |c: f32| c.max(0.0).powf(2.2);
+    RgbFloat::new(decode(r), decode(g), decode(b))
+}
+
+fn transform(matrix: &[[f32; 3]; 3], color: &RgbFloat) -> [f32; 3] {
+    matrix.map(|row| row[0] * color.r() + row[1] * color.g() + row[2] * color.b())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TONE_MAPPERS: [ToneMapper; 5] = [
+        ToneMapper::Clamp,
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard,
+        ToneMapper::Aces,
+        ToneMapper::Agx,
+    ];
+
+    fn map(tone_mapping: &ToneMapping, value: f32) -> f32 {
+        let img = Rgb32FImage::from_pixel(1, 1, image::Rgb([value; 3]));
+        tone_mapping.apply(&img).get_pixel(0, 0).0[0]
+    }
+
+    #[test]
+    fn tone_mappers_are_monotonic_and_bounded() {
+        for tone_mapper in TONE_MAPPERS {
+            let tone_mapping =
+                ToneMapping { tone_mapper, white_point: Some(16.0), ..Default::default() };
+            assert!(map(&tone_mapping, 0.0) < 0.01, "{:?}", tone_mapper);
+            let mut previous = 0.0;
+            for value in [0.01, 0.1, 0.5, 1.0, 2.0, 8.0, 100.0] {
+                let mapped = map(&tone_mapping, value);
+                assert!((previous..=1.0).contains(&mapped), "{:?}: {}", tone_mapper, mapped);
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_scales_radiance() {
+        let tone_mapping = ToneMapping { exposure: -2.0, ..Default::default() };
+        assert_eq!(map(&tone_mapping, 2.0), 0.5);
+        assert_eq!(map(&tone_mapping, 8.0), 1.0);
+    }
+
+    #[test]
+    fn extended_reinhard_maps_white_point_to_white() {
+        let tone_mapping = ToneMapping {
+            tone_mapper: ToneMapper::ExtendedReinhard,
+            white_point: Some(4.0),
+            ..Default::default()
+        };
+        assert!((map(&tone_mapping, 4.0) - 1.0).abs() < 1e-5);
+        assert!(map(&tone_mapping, 1.0) > 0.5);
+
+        // Without a white point, the brightest pixel becomes white.
+        let tone_mapping = ToneMapping { white_point: None, ..tone_mapping };
+        let mut img = Rgb32FImage::from_pixel(2, 1, image::Rgb([1.0; 3]));
+        img.put_pixel(1, 0, image::Rgb([10.0; 3]));
+        let mapped = tone_mapping.apply(&img);
+        assert!((mapped.get_pixel(1, 0).0[0] - 1.0).abs() < 1e-5);
+    }
+}